@@ -0,0 +1,217 @@
+use crate::profile::Profile;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, WebviewWindow};
+
+/// Cache folders WebKit creates in the webview data folder, which wry also
+/// hands it as the cache directory: the HTTP cache and the DOM cache storage.
+const CACHE_DIRS: &[&str] = &["WebKitCache", "CacheStorage"];
+
+/// What to remove from the webview's storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataKind {
+    /// HTTP, memory and script caches; the login is kept
+    Cache,
+    /// Cookies and site storage, which signs out of every account
+    Session,
+    /// Everything the webview stores
+    All,
+}
+
+impl DataKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            DataKind::Cache => "the cache",
+            DataKind::Session => "cookies and site data",
+            DataKind::All => "all browsing data",
+        }
+    }
+}
+
+/// Clears `kind` from the window's webview and then calls `on_done`.
+#[cfg(target_os = "linux")]
+pub fn clear<R: tauri::Runtime>(
+    window: &WebviewWindow<R>,
+    kind: DataKind,
+    on_done: impl FnOnce(Result<(), String>) + Send + 'static,
+) -> Result<(), String> {
+    use webkit2gtk::{WebViewExt, WebsiteDataManagerExtManual, WebsiteDataTypes};
+
+    let types = match kind {
+        DataKind::Cache => {
+            WebsiteDataTypes::MEMORY_CACHE
+                | WebsiteDataTypes::DISK_CACHE
+                | WebsiteDataTypes::OFFLINE_APPLICATION_CACHE
+                | WebsiteDataTypes::DOM_CACHE
+        }
+        DataKind::Session => {
+            WebsiteDataTypes::COOKIES
+                | WebsiteDataTypes::SESSION_STORAGE
+                | WebsiteDataTypes::LOCAL_STORAGE
+                | WebsiteDataTypes::INDEXEDDB_DATABASES
+                | WebsiteDataTypes::WEBSQL_DATABASES
+        }
+        DataKind::All => WebsiteDataTypes::ALL,
+    };
+
+    window
+        .with_webview(move |webview| {
+            let Some(manager) = webview.inner().website_data_manager() else {
+                on_done(Err("the webview has no data manager".to_string()));
+                return;
+            };
+            // A time span of zero means "since the beginning"
+            manager.clear(
+                types,
+                webkit2gtk::glib::TimeSpan(0),
+                None::<&webkit2gtk::gio::Cancellable>,
+                move |result| on_done(result.map_err(|e| e.to_string())),
+            );
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Clears `kind` from the window's webview and then calls `on_done`.
+#[cfg(not(target_os = "linux"))]
+pub fn clear<R: tauri::Runtime>(
+    window: &WebviewWindow<R>,
+    kind: DataKind,
+    on_done: impl FnOnce(Result<(), String>) + Send + 'static,
+) -> Result<(), String> {
+    let result = match kind {
+        DataKind::Cache => Err("clearing only the cache is not supported here".to_string()),
+        DataKind::Session => window
+            .cookies()
+            .and_then(|cookies| {
+                cookies
+                    .into_iter()
+                    .try_for_each(|cookie| window.delete_cookie(cookie))
+            })
+            .map_err(|e| e.to_string()),
+        DataKind::All => window.clear_all_browsing_data().map_err(|e| e.to_string()),
+    };
+    on_done(result);
+    Ok(())
+}
+
+/// The webview's data folder (cookies, storage and caches) for the running profile.
+pub fn data_dir<R: tauri::Runtime>(app: &AppHandle<R>) -> PathBuf {
+    app.state::<Profile>().data_dir().join("webview-cache")
+}
+
+/// Bytes used by the webview's data folder, caches included.
+pub fn total_size<R: tauri::Runtime>(app: &AppHandle<R>) -> u64 {
+    dir_size(&data_dir(app))
+}
+
+/// Deletes the HTTP cache when it has grown past `limit_mb`; 0 means no limit.
+///
+/// Runs before the webview starts, while WebKit is not using the folder.
+pub fn prune_http_cache<R: tauri::Runtime>(app: &AppHandle<R>, limit_mb: u64) {
+    prune_cache_dirs(&data_dir(app), limit_mb);
+}
+
+fn prune_cache_dirs(data_dir: &Path, limit_mb: u64) {
+    let dirs: Vec<PathBuf> = CACHE_DIRS.iter().map(|dir| data_dir.join(dir)).collect();
+    let size: u64 = dirs.iter().map(|dir| dir_size(dir)).sum();
+    if limit_mb == 0 || size <= limit_mb * 1024 * 1024 {
+        return;
+    }
+
+    for dir in &dirs {
+        match fs::remove_dir_all(dir) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                eprintln!("Failed to clear the HTTP cache: {err}");
+                return;
+            }
+        }
+    }
+    eprintln!(
+        "Cleared the HTTP cache ({}, limit {limit_mb} MB)",
+        format_size(size)
+    );
+}
+
+/// Total size of the files below `path`, not following symlinks.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Formats a byte count for people, e.g. `12.3 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_readable() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 / 2), "1.5 GB");
+    }
+
+    #[test]
+    fn dir_size_counts_nested_files() {
+        let dir = std::env::temp_dir().join(format!("chatgpt-desktop-size-{}", std::process::id()));
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("one"), [0u8; 10]).unwrap();
+        fs::write(dir.join("a/b/two"), [0u8; 32]).unwrap();
+
+        assert_eq!(dir_size(&dir), 42);
+        assert_eq!(dir_size(&dir.join("missing")), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_only_removes_the_caches() {
+        let dir =
+            std::env::temp_dir().join(format!("chatgpt-desktop-prune-{}", std::process::id()));
+        fs::create_dir_all(dir.join("WebKitCache/Version 16")).unwrap();
+        fs::create_dir_all(dir.join("localstorage")).unwrap();
+        fs::write(
+            dir.join("WebKitCache/Version 16/record"),
+            vec![0u8; 2 * 1024 * 1024],
+        )
+        .unwrap();
+        fs::write(dir.join("localstorage/chatgpt.com"), vec![0u8; 1024 * 1024]).unwrap();
+        fs::write(dir.join("cookies.sqlite"), [0u8; 16]).unwrap();
+
+        // At the limit, and no limit at all
+        prune_cache_dirs(&dir, 2);
+        prune_cache_dirs(&dir, 0);
+        assert!(dir.join("WebKitCache").exists());
+
+        prune_cache_dirs(&dir, 1);
+        assert!(!dir.join("WebKitCache").exists());
+        assert!(dir.join("localstorage/chatgpt.com").exists());
+        assert!(dir.join("cookies.sqlite").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
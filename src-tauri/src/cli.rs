@@ -0,0 +1,255 @@
+use crate::profile;
+use crate::url_policy::UrlPolicy;
+use std::path::PathBuf;
+use url::Url;
+
+pub const USAGE: &str = "\
+Usage: chatgpt-desktop [OPTIONS] [PROMPT]...
+
+Opens ChatGPT in a desktop window. If the app is already running, the request
+is handed to the running window instead of starting a second one.
+
+Arguments:
+  [PROMPT]...              Start a new chat with this text in the message box
+
+Options:
+      --conversation <ID>  Open the conversation https://chatgpt.com/c/<ID>
+      --url <URL>          Open a ChatGPT or OpenAI URL instead of the start page
+      --hidden             Start in the tray without showing the window
+      --profile <NAME>     Use a separate profile with its own login and settings
+      --incognito          Open a separate window whose login and cache are deleted on exit
+      --data-dir <DIR>     Keep settings and login data in DIR (portable mode)
+  -h, --help               Print this help
+  -V, --version            Print the version
+";
+
+/// What a launch asked the app to do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaunchArgs {
+    pub prompt: Option<String>,
+    pub conversation: Option<String>,
+    pub url: Option<Url>,
+    pub hidden: bool,
+    pub profile: Option<String>,
+    pub incognito: bool,
+    pub data_dir: Option<PathBuf>,
+}
+
+pub enum Command {
+    Launch(LaunchArgs),
+    Help,
+    Version,
+}
+
+impl LaunchArgs {
+    /// Whether the launch asks for a specific page rather than just the window.
+    pub fn opens_page(&self) -> bool {
+        self.prompt.is_some() || self.conversation.is_some() || self.url.is_some()
+    }
+
+    /// The page to load for this launch. Conversations live under `service`,
+    /// everything else without an explicit `--url` opens `start`.
+    pub fn target_url(&self, service: &Url, start: &Url) -> Url {
+        if let Some(url) = &self.url {
+            return url.clone();
+        }
+        match &self.conversation {
+            Some(id) => service
+                .join(&format!("/c/{id}"))
+                .unwrap_or_else(|_| service.clone()),
+            None => start.clone(),
+        }
+    }
+}
+
+/// Parses the arguments after the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut launch = LaunchArgs::default();
+    let mut prompt_words: Vec<String> = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            inline_value
+                .map(str::to_string)
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{name} needs a value"))
+        };
+        let switch = |name: &str| -> Result<bool, String> {
+            match inline_value {
+                Some(_) => Err(format!("{name} does not take a value")),
+                None => Ok(true),
+            }
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => {
+                switch("--help")?;
+                return Ok(Command::Help);
+            }
+            "-V" | "--version" => {
+                switch("--version")?;
+                return Ok(Command::Version);
+            }
+            "--hidden" => launch.hidden = switch("--hidden")?,
+            "--incognito" => launch.incognito = switch("--incognito")?,
+            "--data-dir" => {
+                let dir = value("--data-dir")?;
+                if dir.is_empty() {
+                    return Err("--data-dir needs a directory".to_string());
+                }
+                launch.data_dir = Some(PathBuf::from(dir));
+            }
+            "--profile" => {
+                let name = value("--profile")?;
+                if !profile::is_valid_name(&name) {
+                    return Err(format!(
+                        "'{name}' is not a valid profile name (use letters, digits, '-' and '_')"
+                    ));
+                }
+                launch.profile = Some(name);
+            }
+            "--conversation" => {
+                let id = value("--conversation")?;
+                if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                    return Err(format!("'{id}' is not a valid conversation id"));
+                }
+                launch.conversation = Some(id);
+            }
+            "--url" => {
+                let raw = value("--url")?;
+                let url = Url::parse(&raw).map_err(|e| format!("invalid URL '{raw}': {e}"))?;
+                if !UrlPolicy::default().allows_page(&url) {
+                    return Err(format!("{url} is not a ChatGPT URL"));
+                }
+                launch.url = Some(url);
+            }
+            "--" => prompt_words.extend(args.by_ref()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ => prompt_words.push(arg),
+        }
+    }
+
+    if launch.url.is_some() && launch.conversation.is_some() {
+        return Err("--url and --conversation cannot be used together".to_string());
+    }
+
+    let prompt = prompt_words.join(" ");
+    if !prompt.trim().is_empty() {
+        launch.prompt = Some(prompt);
+    }
+
+    Ok(Command::Launch(launch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launch(args: &[&str]) -> Result<LaunchArgs, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            Command::Launch(launch) => Ok(launch),
+            Command::Help => Err("help".to_string()),
+            Command::Version => Err("version".to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_launch_arguments() {
+        let url = |raw: &str| Some(Url::parse(raw).unwrap());
+        let cases: &[(&[&str], LaunchArgs)] = &[
+            (&[], LaunchArgs::default()),
+            (
+                &["hello", "world"],
+                LaunchArgs {
+                    prompt: Some("hello world".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                &["--conversation", "abc-123"],
+                LaunchArgs {
+                    conversation: Some("abc-123".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                &["--conversation=abc-123", "--hidden"],
+                LaunchArgs {
+                    conversation: Some("abc-123".to_string()),
+                    hidden: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                &["summarize", "--url", "https://chatgpt.com/g/g-1", "this"],
+                LaunchArgs {
+                    prompt: Some("summarize this".to_string()),
+                    url: url("https://chatgpt.com/g/g-1"),
+                    ..Default::default()
+                },
+            ),
+            (
+                &["--profile=work", "--", "--hidden", "is", "a", "word"],
+                LaunchArgs {
+                    prompt: Some("--hidden is a word".to_string()),
+                    profile: Some("work".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(launch(args).as_ref(), Ok(expected), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--conversation"], "--conversation needs a value"),
+            (&["--conversation="], "'' is not a valid conversation id"),
+            (
+                &["--conversation", "../x"],
+                "'../x' is not a valid conversation id",
+            ),
+            (&["--frobnicate"], "unknown option '--frobnicate'"),
+            (&["-x"], "unknown option '-x'"),
+            (&["--hidden=x"], "--hidden does not take a value"),
+            (&["--hidden="], "--hidden does not take a value"),
+            (&["--incognito=yes"], "--incognito does not take a value"),
+            (&["--help=x"], "--help does not take a value"),
+            (
+                &["--profile", "a/b"],
+                "'a/b' is not a valid profile name (use letters, digits, '-' and '_')",
+            ),
+            (
+                &["--url", "https://example.com/"],
+                "https://example.com/ is not a ChatGPT URL",
+            ),
+            (
+                &["--url", "https://chatgpt.com/", "--conversation", "abc"],
+                "--url and --conversation cannot be used together",
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(
+                launch(args).as_ref(),
+                Err(&expected.to_string()),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn help_and_version_win() {
+        assert!(matches!(
+            parse(["x".into(), "-h".into()]),
+            Ok(Command::Help)
+        ));
+        assert!(matches!(parse(["--version".into()]), Ok(Command::Version)));
+    }
+}
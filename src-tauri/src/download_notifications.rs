@@ -0,0 +1,205 @@
+use crate::downloads::{Download, DownloadStatus};
+use crate::settings_store::SettingsStore;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+/// Shows the notification for a download that started or ended, if enabled.
+///
+/// On Linux a completed download can be opened or shown in its folder from the
+/// notification, and a failed one can be tried again.
+pub fn notify<R: tauri::Runtime>(app: &AppHandle<R>, download: Download) {
+    if !app.state::<SettingsStore>().get().notifications_enabled {
+        return;
+    }
+
+    let (title, body) = match download.status {
+        DownloadStatus::InProgress => {
+            ("Downloading file", format!("Saving: {}", download.filename))
+        }
+        DownloadStatus::Completed => (
+            "Download completed",
+            format!("Saved to: {}", download.destination.display()),
+        ),
+        DownloadStatus::Failed => (
+            "Download failed",
+            format!("Could not download {}", download.filename),
+        ),
+    };
+
+    #[cfg(target_os = "linux")]
+    if download.status != DownloadStatus::InProgress {
+        actions::show(app.clone(), title, body, download);
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let _ = app.notification().builder().title(title).body(body).show();
+    });
+}
+
+#[cfg(target_os = "linux")]
+pub use actions::DownloadActions;
+
+#[cfg(target_os = "linux")]
+mod actions {
+    use crate::downloads::{Download, DownloadStatus};
+    use crate::file_manager;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::thread;
+    use tauri::{AppHandle, Manager};
+    use zbus::blocking::{Connection, MessageIterator};
+    use zbus::message::Type;
+    use zbus::zvariant::Value;
+    use zbus::MatchRule;
+
+    const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+    const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+    const OPEN: &str = "open";
+    const SHOW_IN_FOLDER: &str = "show-in-folder";
+    const RETRY: &str = "retry";
+
+    /// Talks to the notification server for download notifications with buttons.
+    ///
+    /// The notification plugin has no buttons on desktop. One connection sends
+    /// every notification and a single thread handles the clicks on all of them.
+    #[derive(Default)]
+    pub struct DownloadActions {
+        /// Opened with the first notification; None when there is no session bus
+        connection: OnceLock<Option<Connection>>,
+        /// Downloads whose notification is still open, by notification id
+        pending: Arc<Mutex<HashMap<u32, Download>>>,
+    }
+
+    impl DownloadActions {
+        fn connection<R: tauri::Runtime>(&self, app: &AppHandle<R>) -> Option<&Connection> {
+            self.connection
+                .get_or_init(|| {
+                    let connection = Connection::session()
+                        .map_err(|err| eprintln!("Failed to connect to the session bus: {err}"))
+                        .ok()?;
+                    let app = app.clone();
+                    let listener = connection.clone();
+                    let pending = self.pending.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = listen(&app, &listener, &pending) {
+                            eprintln!("Stopped listening for notification clicks: {err}");
+                        }
+                    });
+                    Some(connection)
+                })
+                .as_ref()
+        }
+    }
+
+    /// Shows the notification with buttons matching the download's status.
+    pub fn show<R: tauri::Runtime>(
+        app: AppHandle<R>,
+        title: &'static str,
+        body: String,
+        download: Download,
+    ) {
+        let mut actions = Vec::new();
+        if download.status == DownloadStatus::Completed {
+            actions.extend([OPEN, "Open", SHOW_IN_FOLDER, "Show in Folder"]);
+        } else if download.can_retry() {
+            actions.extend([RETRY, "Retry"]);
+        }
+
+        // Connecting and sending wait on D-Bus, which the download handler must not
+        tauri::async_runtime::spawn_blocking(move || {
+            let state = app.state::<DownloadActions>();
+            let Some(connection) = state.connection(&app) else {
+                return;
+            };
+            let hints: HashMap<&str, Value> = HashMap::new();
+            let id = connection
+                .call_method(
+                    Some(NOTIFICATIONS),
+                    NOTIFICATIONS_PATH,
+                    Some(NOTIFICATIONS),
+                    "Notify",
+                    &(
+                        app.package_info().name.as_str(),
+                        0u32,
+                        app.config().identifier.as_str(),
+                        title,
+                        body.as_str(),
+                        actions,
+                        hints,
+                        -1i32,
+                    ),
+                )
+                .and_then(|reply| reply.body().deserialize::<u32>());
+            match id {
+                Ok(id) => {
+                    state.pending.lock().unwrap().insert(id, download);
+                }
+                Err(err) => eprintln!("Failed to show download notification: {err}"),
+            }
+        });
+    }
+
+    /// Runs the button of a clicked notification and forgets closed ones.
+    fn listen<R: tauri::Runtime>(
+        app: &AppHandle<R>,
+        connection: &Connection,
+        pending: &Mutex<HashMap<u32, Download>>,
+    ) -> zbus::Result<()> {
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(NOTIFICATIONS)?
+            .path(NOTIFICATIONS_PATH)?
+            .build();
+
+        for message in MessageIterator::for_match_rule(rule, connection, None)? {
+            // One malformed signal from another notifier must not end the listener
+            let message = match message {
+                Ok(message) => message,
+                Err(err) => {
+                    eprintln!("Failed to read notification signal: {err}");
+                    continue;
+                }
+            };
+            let header = message.header();
+            match header.member().map(|member| member.as_str()) {
+                Some("ActionInvoked") => {
+                    let (id, action) = match message.body().deserialize::<(u32, String)>() {
+                        Ok(body) => body,
+                        Err(err) => {
+                            eprintln!("Failed to read ActionInvoked signal: {err}");
+                            continue;
+                        }
+                    };
+                    let Some(download) = pending.lock().unwrap().remove(&id) else {
+                        continue;
+                    };
+                    let result = match action.as_str() {
+                        OPEN => file_manager::open_file(&download.destination),
+                        SHOW_IN_FOLDER => file_manager::show_in_folder(&download.destination),
+                        RETRY => crate::redownload(app, &download),
+                        _ => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        eprintln!("Download action {action} failed: {err}");
+                    }
+                }
+                // Dismissed or timed out
+                Some("NotificationClosed") => {
+                    let (id, _reason) = match message.body().deserialize::<(u32, u32)>() {
+                        Ok(body) => body,
+                        Err(err) => {
+                            eprintln!("Failed to read NotificationClosed signal: {err}");
+                            continue;
+                        }
+                    };
+                    pending.lock().unwrap().remove(&id);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,805 @@
+use crate::settings::{atomic_write, Settings};
+use crate::url_policy::glob_matches;
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::webview::DownloadEvent;
+use tauri::WebviewWindow;
+
+/// Appended to the file name while a download is still being written.
+const PART_SUFFIX: &str = ".part";
+
+/// Used when the server suggests no usable file name.
+const DEFAULT_FILENAME: &str = "download";
+
+/// Most downloads kept in the history; the oldest are dropped first.
+const MAX_HISTORY: usize = 500;
+
+/// A folder for files of some types, from the `download_rules` setting.
+///
+/// A rule matches when the file has one of `extensions` or when the MIME type
+/// guessed from its name matches `mime`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadRule {
+    /// Extensions without the dot, e.g. `["csv", "xlsx"]`
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// A MIME type where `*` is a wildcard, e.g. `image/*`
+    #[serde(default)]
+    pub mime: Option<String>,
+    /// `~` is the home folder, relative paths are inside the download folder
+    pub dir: String,
+}
+
+impl DownloadRule {
+    fn matches(&self, filename: &str) -> bool {
+        let extension = Path::new(filename).extension().and_then(OsStr::to_str);
+        let by_extension = extension.is_some_and(|extension| {
+            self.extensions
+                .iter()
+                .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+        });
+        by_extension
+            || self.mime.as_deref().is_some_and(|pattern| {
+                let pattern = pattern.trim().to_ascii_lowercase();
+                mime_guess::from_path(filename)
+                    .iter_raw()
+                    .any(|mime| glob_matches(&pattern, mime))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+/// One entry of the download history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Download {
+    pub id: u64,
+    pub filename: String,
+    pub url: String,
+    /// Where the file ends up; until it completes it is written to the `.part` file next to it
+    pub destination: PathBuf,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub status: DownloadStatus,
+    /// Size of the saved file, known once the download completed
+    pub size: Option<u64>,
+}
+
+impl Download {
+    /// Whether the file can be downloaded again. Blob addresses only live as
+    /// long as the page that created them.
+    pub fn can_retry(&self) -> bool {
+        !self.url.starts_with("blob:")
+    }
+}
+
+/// Keeps track of every download separately, so overlapping downloads each
+/// report their own file, and remembers them as the download history.
+///
+/// The webview identifies a download only by its URL, so a `Finished` event is
+/// matched to the oldest unfinished download of that URL (and destination, when
+/// the event has one).
+#[derive(Default)]
+pub struct DownloadRegistry {
+    downloads: Mutex<Vec<Download>>,
+    /// Paths handed out by [`Self::reserve`] whose download has not started yet
+    reserved: Mutex<Vec<PathBuf>>,
+    /// File the history is saved to; None keeps it in memory only
+    path: Option<PathBuf>,
+}
+
+impl DownloadRegistry {
+    /// Loads the history saved at `path` and saves every change back to it.
+    pub fn load(path: PathBuf) -> Self {
+        let mut downloads: Vec<Download> = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                eprintln!("Ignoring broken download history {}: {err}", path.display());
+                Vec::new()
+            }),
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                eprintln!("Failed to read download history {}: {err}", path.display());
+                Vec::new()
+            }
+        };
+
+        // These were cut off when the app last quit
+        for download in &mut downloads {
+            if download.status == DownloadStatus::InProgress {
+                download.status = DownloadStatus::Failed;
+                let _ = fs::remove_file(part_path(&download.destination));
+            }
+        }
+        Self {
+            downloads: Mutex::new(downloads),
+            reserved: Mutex::new(Vec::new()),
+            path: Some(path),
+        }
+    }
+
+    fn save(&self, downloads: &[Download]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(downloads)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| atomic_write(path, &contents));
+        if let Err(err) = result {
+            eprintln!("Failed to save download history {}: {err}", path.display());
+        }
+    }
+
+    /// Updates the registry from a webview event and returns the affected download.
+    ///
+    /// For `Requested`, `destination_for` turns the [file name suggested by the
+    /// server](suggested_filename) into the path to save to, usually through [`Self::reserve`],
+    /// and the webview is told to write to its `.part` file. Without a path the
+    /// download is not recorded.
+    pub fn record(
+        &self,
+        event: &mut DownloadEvent<'_>,
+        destination_for: impl FnOnce(&str) -> Option<PathBuf>,
+    ) -> Option<Download> {
+        match event {
+            DownloadEvent::Requested { url, destination } => {
+                let filename = suggested_filename(destination);
+                let final_path = destination_for(&filename)?;
+                **destination = part_path(&final_path);
+                Some(self.start(url.as_str(), final_path))
+            }
+            DownloadEvent::Finished { url, path, success } => {
+                self.finish(url.as_str(), path.as_deref(), *success)
+            }
+            _ => None,
+        }
+    }
+
+    /// A path in `dir` for `filename` that overwrites neither an existing file
+    /// nor another running or reserved download, e.g. `report (1).pdf`.
+    ///
+    /// The path stays reserved until a download [starts](Self::start) with it or
+    /// it is [released](Self::release), so a second download asking for the same
+    /// name while a save dialog is open gets another one.
+    pub fn reserve(&self, dir: &Path, filename: &str) -> PathBuf {
+        let downloads = self.downloads.lock().unwrap();
+        let mut reserved = self.reserved.lock().unwrap();
+        let taken = |path: &Path| {
+            // symlink_metadata also sees dangling links, which would be written through
+            path.symlink_metadata().is_ok()
+                || part_path(path).symlink_metadata().is_ok()
+                || reserved.iter().any(|reserved| reserved == path)
+                || downloads
+                    .iter()
+                    .any(|d| d.status == DownloadStatus::InProgress && d.destination == path)
+        };
+
+        let filename = sanitize_filename(filename);
+        let (stem, extension) = split_extension(&filename);
+        let path = std::iter::once(dir.join(&filename))
+            .chain((1..).map(|n| dir.join(format!("{stem} ({n}){extension}"))))
+            .find(|candidate| !taken(candidate))
+            .unwrap();
+        reserved.push(path.clone());
+        path
+    }
+
+    /// Gives up a path from [`Self::reserve`] that no download will use.
+    pub fn release(&self, path: &Path) {
+        self.reserved
+            .lock()
+            .unwrap()
+            .retain(|reserved| reserved != path);
+    }
+
+    pub fn start(&self, url: &str, destination: PathBuf) -> Download {
+        let mut downloads = self.downloads.lock().unwrap();
+        self.release(&destination);
+        let download = Download {
+            id: downloads.iter().map(|d| d.id).max().unwrap_or_default() + 1,
+            filename: destination
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            url: url.to_string(),
+            destination,
+            started_at: now(),
+            status: DownloadStatus::InProgress,
+            size: None,
+        };
+        downloads.push(download.clone());
+        let excess = downloads.len().saturating_sub(MAX_HISTORY);
+        downloads.drain(..excess);
+        self.save(&downloads);
+        download
+    }
+
+    /// Marks a download as ended. A successful one is moved from its `.part`
+    /// file to the destination, a failed one has its `.part` file removed.
+    pub fn finish(&self, url: &str, path: Option<&Path>, success: bool) -> Option<Download> {
+        let mut downloads = self.downloads.lock().unwrap();
+        let pending = |d: &Download| d.status == DownloadStatus::InProgress && d.url == url;
+        let index = downloads
+            .iter()
+            .position(|d| {
+                pending(d)
+                    && path.is_none_or(|path| {
+                        d.destination == path || part_path(&d.destination) == path
+                    })
+            })
+            // The webview may report a different path than the one it was given
+            .or_else(|| downloads.iter().position(pending))?;
+        let download = &mut downloads[index];
+        let part = part_path(&download.destination);
+
+        if success {
+            match fs::rename(&part, &download.destination) {
+                Ok(()) => {
+                    download.status = DownloadStatus::Completed;
+                    download.size = fs::metadata(&download.destination).ok().map(|m| m.len());
+                }
+                Err(err) => {
+                    eprintln!("Failed to move {} into place: {err}", part.display());
+                    download.status = DownloadStatus::Failed;
+                }
+            }
+        } else {
+            download.status = DownloadStatus::Failed;
+            if let Err(err) = fs::remove_file(&part) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Failed to delete {}: {err}", part.display());
+                }
+            }
+        }
+        let download = download.clone();
+        self.save(&downloads);
+        Some(download)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Download> {
+        let downloads = self.downloads.lock().unwrap();
+        downloads.iter().find(|d| d.id == id).cloned()
+    }
+
+    /// Downloads whose file name or address contains `query`, newest first.
+    /// An empty query returns the whole history.
+    pub fn search(&self, query: &str) -> Vec<Download> {
+        let query = query.trim().to_lowercase();
+        let downloads = self.downloads.lock().unwrap();
+        downloads
+            .iter()
+            .rev()
+            .filter(|d| {
+                d.filename.to_lowercase().contains(&query) || d.url.to_lowercase().contains(&query)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Removes a download from the history; the file itself is kept.
+    pub fn remove(&self, id: u64) -> Option<Download> {
+        let mut downloads = self.downloads.lock().unwrap();
+        let index = downloads.iter().position(|d| d.id == id)?;
+        let download = downloads.remove(index);
+        self.save(&downloads);
+        Some(download)
+    }
+}
+
+/// The folder to save `filename` to: the first matching rule, then the
+/// `download_dir` setting, then `default_dir` (the system Downloads folder).
+pub fn folder_for(settings: &Settings, filename: &str, home: &Path, default_dir: &Path) -> PathBuf {
+    let base = match settings.download_dir.as_deref() {
+        Some(dir) if !dir.trim().is_empty() => expand_dir(dir, home, default_dir),
+        _ => default_dir.to_path_buf(),
+    };
+    settings
+        .download_rules
+        .iter()
+        .find(|rule| !rule.dir.trim().is_empty() && rule.matches(filename))
+        .map_or(base.clone(), |rule| expand_dir(&rule.dir, home, &base))
+}
+
+/// Resolves `~` to `home` and relative paths against `base`.
+fn expand_dir(dir: &str, home: &Path, base: &Path) -> PathBuf {
+    let dir = dir.trim();
+    match dir.strip_prefix('~') {
+        Some("") => home.to_path_buf(),
+        Some(rest) if rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => base.join(dir),
+    }
+}
+
+/// Shows a save dialog for a download and returns the chosen path, or None
+/// when it was cancelled.
+///
+/// The webview needs the destination before the download handler returns, so
+/// the GTK main loop keeps running here until the dialog closes, the same way
+/// a modal dialog does.
+#[cfg(target_os = "linux")]
+pub fn ask_save_path<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    proposed: &Path,
+) -> Option<PathBuf> {
+    use std::sync::mpsc::{self, TryRecvError};
+    use tauri::Manager;
+    use tauri_plugin_dialog::DialogExt;
+    use webkit2gtk::glib::MainContext;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut dialog = app.dialog().file().set_title("Save Download");
+    if let Some(dir) = proposed.parent() {
+        dialog = dialog.set_directory(dir);
+    }
+    if let Some(name) = proposed.file_name() {
+        dialog = dialog.set_file_name(name.to_string_lossy());
+    }
+    if let Some(window) = app.get_webview_window("main") {
+        dialog = dialog.set_parent(&window);
+    }
+    dialog.save_file(move |path| {
+        let _ = sender.send(path);
+        MainContext::default().wakeup();
+    });
+
+    let context = MainContext::default();
+    loop {
+        match receiver.try_recv() {
+            Ok(path) => return path.and_then(|path| path.into_path().ok()),
+            Err(TryRecvError::Empty) => {
+                context.iteration(true);
+            }
+            Err(TryRecvError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Shows a save dialog for a download and returns the chosen path, or None
+/// when it was cancelled.
+///
+/// Only implemented for WebKitGTK. Elsewhere the download is saved to
+/// `proposed` as if `ask_download_location` were off.
+#[cfg(not(target_os = "linux"))]
+pub fn ask_save_path<R: tauri::Runtime>(
+    _app: &tauri::AppHandle<R>,
+    proposed: &Path,
+) -> Option<PathBuf> {
+    eprintln!("ask_download_location is only supported on Linux, saving to the download folder");
+    Some(proposed.to_path_buf())
+}
+
+/// Downloads `url` again in the window's webview, as if its link had been clicked.
+#[cfg(target_os = "linux")]
+pub fn start_download<R: tauri::Runtime>(
+    window: &WebviewWindow<R>,
+    url: &str,
+) -> Result<(), String> {
+    use webkit2gtk::WebViewExt;
+
+    let url = url.to_string();
+    window
+        .with_webview(move |webview| {
+            webview.inner().download_uri(&url);
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Downloads `url` again in the window's webview, as if its link had been clicked.
+#[cfg(not(target_os = "linux"))]
+pub fn start_download<R: tauri::Runtime>(
+    window: &WebviewWindow<R>,
+    url: &str,
+) -> Result<(), String> {
+    let url = serde_json::to_string(url).map_err(|e| e.to_string())?;
+    window
+        .eval(format!(
+            "(() => {{ const a = document.createElement('a'); a.href = {url}; a.download = ''; a.click(); }})();"
+        ))
+        .map_err(|e| e.to_string())
+}
+
+/// The file a download is written to before it completes.
+pub fn part_path(destination: &Path) -> PathBuf {
+    let mut path = OsString::from(destination);
+    path.push(PART_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Reduces a file name suggested by a server to a single path component, so a
+/// download cannot be saved outside the folder it is meant for.
+pub fn sanitize_filename(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .map(str::trim)
+        .find(|part| !part.is_empty() && *part != "." && *part != "..")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+
+    if name.is_empty() {
+        DEFAULT_FILENAME.to_string()
+    } else {
+        name
+    }
+}
+
+/// Recovers the server's file name from the path the webview picked for it.
+///
+/// The webview already numbers the name against the system Downloads folder,
+/// so `report (1).pdf` becomes `report.pdf` again when that file exists there;
+/// [`DownloadRegistry::reserve`] numbers it against the folder actually used.
+pub fn suggested_filename(destination: &Path) -> String {
+    let filename = sanitize_filename(&destination.to_string_lossy());
+    let Some(dir) = destination.parent() else {
+        return filename;
+    };
+    let (stem, extension) = split_extension(&filename);
+    let original = stem
+        .strip_suffix(')')
+        .and_then(|stem| stem.rsplit_once(" ("))
+        .filter(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        .map(|(base, _)| format!("{base}{extension}"))
+        .filter(|original| dir.join(original).symlink_metadata().is_ok());
+    original.unwrap_or(filename)
+}
+
+/// Splits `archive.tar.gz` into `archive` and `.tar.gz`, so a number can go in between.
+fn split_extension(filename: &str) -> (&str, &str) {
+    let Some(dot) = filename.rfind('.').filter(|&dot| dot > 0) else {
+        return (filename, "");
+    };
+    let stem = &filename[..dot];
+    match stem.strip_suffix(".tar").filter(|base| !base.is_empty()) {
+        Some(base) => (base, &filename[base.len()..]),
+        None => (stem, &filename[dot..]),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chatgpt-desktop-dl-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Records a `Requested` event for `destination` and, like the webview,
+    /// starts writing the part file it is handed.
+    fn requested(registry: &DownloadRegistry, url: &str, destination: &Path) -> Download {
+        let mut part = destination.to_path_buf();
+        let download = registry
+            .record(
+                &mut DownloadEvent::Requested {
+                    url: Url::parse(url).unwrap(),
+                    destination: &mut part,
+                },
+                |_| Some(destination.to_path_buf()),
+            )
+            .unwrap();
+        fs::write(&part, "").unwrap();
+        download
+    }
+
+    /// Records a `Requested` event for a file the registry places in `dir`, and
+    /// returns the download and the path the webview got.
+    fn requested_in(
+        registry: &DownloadRegistry,
+        url: &str,
+        dir: &Path,
+        suggested: &str,
+    ) -> (Download, PathBuf) {
+        let mut destination = PathBuf::from(suggested);
+        let download = registry
+            .record(
+                &mut DownloadEvent::Requested {
+                    url: Url::parse(url).unwrap(),
+                    destination: &mut destination,
+                },
+                |filename| Some(registry.reserve(dir, filename)),
+            )
+            .unwrap();
+        (download, destination)
+    }
+
+    fn finished(
+        registry: &DownloadRegistry,
+        url: &str,
+        path: Option<&Path>,
+        success: bool,
+    ) -> Option<Download> {
+        registry.record(
+            &mut DownloadEvent::Finished {
+                url: Url::parse(url).unwrap(),
+                path: path.map(Path::to_path_buf),
+                success,
+            },
+            |_| unreachable!(),
+        )
+    }
+
+    #[test]
+    fn overlapping_downloads_keep_their_own_destination() {
+        let dl = temp_dir("overlap");
+        let registry = DownloadRegistry::default();
+        let a = requested(&registry, "https://files.example/a", &dl.join("a.pdf"));
+        let b = requested(&registry, "https://files.example/b", &dl.join("b.png"));
+        assert_ne!(a.id, b.id);
+
+        let done = finished(
+            &registry,
+            "https://files.example/a",
+            Some(&dl.join("a.pdf")),
+            true,
+        )
+        .unwrap();
+        assert_eq!(done.id, a.id);
+        assert_eq!(done.destination, dl.join("a.pdf"));
+        assert_eq!(done.status, DownloadStatus::Completed);
+        assert_eq!(
+            registry.get(b.id).unwrap().status,
+            DownloadStatus::InProgress
+        );
+
+        let failed = finished(&registry, "https://files.example/b", None, false).unwrap();
+        assert_eq!(failed.destination, dl.join("b.png"));
+        assert_eq!(failed.status, DownloadStatus::Failed);
+        assert_eq!(failed.size, None);
+        fs::remove_dir_all(dl).unwrap();
+    }
+
+    #[test]
+    fn same_url_twice_finishes_in_order_or_by_path() {
+        let dl = temp_dir("twice");
+        let registry = DownloadRegistry::default();
+        let first = requested(&registry, "https://files.example/x", &dl.join("x.txt"));
+        let second = requested(&registry, "https://files.example/x", &dl.join("x (1).txt"));
+
+        let done = finished(
+            &registry,
+            "https://files.example/x",
+            Some(&dl.join("x (1).txt")),
+            true,
+        );
+        assert_eq!(done.unwrap().id, second.id);
+        let done = finished(&registry, "https://files.example/x", None, true);
+        assert_eq!(done.unwrap().id, first.id);
+        assert!(finished(&registry, "https://files.example/x", None, true).is_none());
+        fs::remove_dir_all(dl).unwrap();
+    }
+
+    #[test]
+    fn unknown_downloads_are_ignored() {
+        let registry = DownloadRegistry::default();
+        assert!(finished(&registry, "https://files.example/never", None, true).is_none());
+        assert!(registry.search("").is_empty());
+    }
+
+    #[test]
+    fn completed_downloads_report_their_size() {
+        let dl = temp_dir("size");
+        let path = dl.join("s.bin");
+
+        let registry = DownloadRegistry::default();
+        requested(&registry, "https://files.example/s", &path);
+        fs::write(part_path(&path), [0u8; 1234]).unwrap();
+        let done = finished(&registry, "https://files.example/s", Some(&path), true).unwrap();
+        assert_eq!(done.size, Some(1234));
+        fs::remove_dir_all(dl).unwrap();
+    }
+
+    #[test]
+    fn running_downloads_keep_their_name_reserved() {
+        let registry = DownloadRegistry::default();
+        let dir = Path::new("/dl");
+        let (first, first_part) = requested_in(&registry, "https://files.example/x", dir, "x.txt");
+        let (second, _) = requested_in(&registry, "https://files.example/y", dir, "x.txt");
+        assert_eq!(first.destination, PathBuf::from("/dl/x.txt"));
+        assert_eq!(first_part, PathBuf::from("/dl/x.txt.part"));
+        assert_eq!(second.destination, PathBuf::from("/dl/x (1).txt"));
+
+        // Held while a save dialog is open, then given back
+        let asked = registry.reserve(dir, "x.txt");
+        assert_eq!(asked, PathBuf::from("/dl/x (2).txt"));
+        assert_eq!(
+            registry.reserve(dir, "x.txt"),
+            PathBuf::from("/dl/x (3).txt")
+        );
+        registry.release(&asked);
+        assert_eq!(registry.reserve(dir, "x.txt"), asked);
+    }
+
+    #[test]
+    fn part_file_is_moved_into_place_or_removed() {
+        let dir = temp_dir("part");
+        let registry = DownloadRegistry::default();
+
+        let (_, part) = requested_in(&registry, "https://files.example/s", &dir, "s.bin");
+        fs::write(&part, [0u8; 1234]).unwrap();
+        let done = finished(&registry, "https://files.example/s", Some(&part), true).unwrap();
+        assert_eq!(done.status, DownloadStatus::Completed);
+        assert_eq!(done.size, Some(1234));
+        assert!(dir.join("s.bin").exists() && !part.exists());
+
+        let (_, part) = requested_in(&registry, "https://files.example/f", &dir, "f.bin");
+        fs::write(&part, [0u8; 10]).unwrap();
+        let failed = finished(&registry, "https://files.example/f", Some(&part), false).unwrap();
+        assert_eq!(failed.status, DownloadStatus::Failed);
+        assert!(!part.exists() && !dir.join("f.bin").exists());
+
+        // Nothing was written, so there is nothing to move
+        let (_, part) = requested_in(&registry, "https://files.example/e", &dir, "e.bin");
+        let failed = finished(&registry, "https://files.example/e", Some(&part), true).unwrap();
+        assert_eq!(failed.status, DownloadStatus::Failed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn existing_files_are_not_overwritten() {
+        let dir = temp_dir("unique");
+        fs::write(dir.join("report.pdf"), "old").unwrap();
+        fs::write(dir.join("report (1).pdf.part"), "").unwrap();
+        fs::write(dir.join("data.tar.gz"), "").unwrap();
+
+        let registry = DownloadRegistry::default();
+        assert_eq!(
+            registry.reserve(&dir, "report.pdf"),
+            dir.join("report (2).pdf")
+        );
+        assert_eq!(
+            registry.reserve(&dir, "data.tar.gz"),
+            dir.join("data (1).tar.gz")
+        );
+        assert_eq!(registry.reserve(&dir, "new.pdf"), dir.join("new.pdf"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn server_filenames_stay_in_the_folder() {
+        let cases = [
+            ("report.pdf", "report.pdf"),
+            ("../../.bashrc", ".bashrc"),
+            ("/etc/passwd", "passwd"),
+            ("..\\..\\Windows\\evil.exe", "evil.exe"),
+            ("dir/..", "dir"),
+            ("..", DEFAULT_FILENAME),
+            ("", DEFAULT_FILENAME),
+            ("line\nbreak.txt", "linebreak.txt"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(sanitize_filename(name), expected, "{name:?}");
+        }
+        assert_eq!(split_extension(".bashrc"), (".bashrc", ""));
+        assert_eq!(split_extension("notes"), ("notes", ""));
+        assert_eq!(split_extension("a.b.c"), ("a.b", ".c"));
+    }
+
+    #[test]
+    fn webview_numbering_is_undone() {
+        let downloads = temp_dir("suggested");
+        let elsewhere = temp_dir("suggested-target");
+        fs::write(downloads.join("report.pdf"), "").unwrap();
+        fs::write(downloads.join("data.tar.gz"), "").unwrap();
+
+        let cases = [
+            ("report (1).pdf", "report.pdf"),
+            ("data (12).tar.gz", "data.tar.gz"),
+            ("notes (1).txt", "notes (1).txt"),
+            ("report (x).pdf", "report (x).pdf"),
+            ("report ().pdf", "report ().pdf"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                suggested_filename(&downloads.join(name)),
+                expected,
+                "{name:?}"
+            );
+        }
+
+        let registry = DownloadRegistry::default();
+        let suggested = downloads.join("report (1).pdf");
+        let (download, _) = requested_in(
+            &registry,
+            "https://example.com/report.pdf",
+            &elsewhere,
+            suggested.to_str().unwrap(),
+        );
+        assert_eq!(download.destination, elsewhere.join("report.pdf"));
+        fs::remove_dir_all(downloads).unwrap();
+        fs::remove_dir_all(elsewhere).unwrap();
+    }
+
+    #[test]
+    fn history_is_saved_and_searchable() {
+        let dir = temp_dir("history");
+        let path = dir.join("download_history.json");
+
+        let registry = DownloadRegistry::load(path.clone());
+        let (report, _) = requested_in(&registry, "https://files.example/r", &dir, "Report.pdf");
+        let (chart, _) = requested_in(&registry, "https://cdn.example/chart", &dir, "chart.png");
+        finished(&registry, "https://files.example/r", None, false);
+
+        // Reloading the history ends downloads that were still running
+        let registry = DownloadRegistry::load(path);
+        let found = registry.search("");
+        assert_eq!(
+            found.iter().map(|d| d.id).collect::<Vec<_>>(),
+            [chart.id, report.id]
+        );
+        assert_eq!(found[0].filename, "chart.png");
+        assert!(found.iter().all(|d| d.status == DownloadStatus::Failed));
+
+        assert_eq!(registry.search("report")[0].id, report.id);
+        assert_eq!(registry.search("CDN.example")[0].id, chart.id);
+        assert!(registry.search("missing").is_empty());
+
+        assert_eq!(registry.remove(report.id).unwrap().id, report.id);
+        assert!(registry.remove(report.id).is_none());
+        let (next, _) = requested_in(&registry, "https://files.example/n", &dir, "n.txt");
+        assert_eq!(next.id, chart.id + 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rules_pick_the_folder() {
+        let settings = Settings {
+            download_dir: Some("~/Inbox".to_string()),
+            download_rules: vec![
+                DownloadRule {
+                    extensions: Vec::new(),
+                    mime: Some("image/*".to_string()),
+                    dir: "~/Pictures/ChatGPT".to_string(),
+                },
+                DownloadRule {
+                    extensions: vec!["csv".to_string(), ".XLSX".to_string()],
+                    mime: None,
+                    dir: "/srv/project/data".to_string(),
+                },
+                DownloadRule {
+                    extensions: vec!["py".to_string(), "rs".to_string()],
+                    mime: None,
+                    dir: "code".to_string(),
+                },
+            ],
+            ..Settings::default()
+        };
+        let home = Path::new("/home/me");
+        let folder =
+            |name: &str| folder_for(&settings, name, home, Path::new("/home/me/Downloads"));
+
+        assert_eq!(folder("chart.png"), home.join("Pictures/ChatGPT"));
+        assert_eq!(folder("photo.JPEG"), home.join("Pictures/ChatGPT"));
+        assert_eq!(folder("table.csv"), PathBuf::from("/srv/project/data"));
+        assert_eq!(folder("sheet.xlsx"), PathBuf::from("/srv/project/data"));
+        assert_eq!(folder("main.rs"), home.join("Inbox/code"));
+        assert_eq!(folder("report.pdf"), home.join("Inbox"));
+        assert_eq!(folder("README"), home.join("Inbox"));
+
+        assert_eq!(
+            folder_for(&Settings::default(), "chart.png", home, Path::new("/dl")),
+            PathBuf::from("/dl")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::link_cleaner::clean_url;
+use crate::settings::Settings;
+use crate::settings_store::SettingsStore;
+use crate::url_policy::host_matches;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::thread;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use url::Url;
+
+/// A browser command for the hosts matching `host`, from the `browser_overrides` setting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrowserOverride {
+    /// Same patterns as `url_rules`: exact, `*.example.com` or `.example.com`
+    pub host: String,
+    pub browser: String,
+}
+
+/// Opens `url` in the configured browser, asking first if `confirm_external_links` is on.
+///
+/// Redirect wrappers and tracking parameters are removed first, so both the
+/// prompt and the browser see the real destination.
+pub fn open<R: tauri::Runtime>(app: &AppHandle<R>, url: &Url) {
+    let settings = app.state::<SettingsStore>().get();
+    let url = clean_url(url, &settings.tracking_parameters);
+    let command = browser_for(&settings, &url).map(str::to_string);
+
+    if !settings.confirm_external_links {
+        launch(command.as_deref(), &url);
+        return;
+    }
+
+    let mut dialog = app
+        .dialog()
+        .message(format!(
+            "This link opens outside ChatGPT Desktop:\n\n{url}\n\nMake sure it goes where you expect."
+        ))
+        .title("Open Link in Browser?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Open".to_string(),
+            "Cancel".to_string(),
+        ));
+    if let Some(window) = app.get_webview_window("main") {
+        dialog = dialog.parent(&window);
+    }
+    dialog.show(move |confirmed| {
+        if confirmed {
+            launch(command.as_deref(), &url);
+        }
+    });
+}
+
+/// The browser command for `url`: the first matching override, then `browser`.
+fn browser_for<'a>(settings: &'a Settings, url: &Url) -> Option<&'a str> {
+    let host = url.host_str().unwrap_or_default();
+    settings
+        .browser_overrides
+        .iter()
+        .find(|o| !host.is_empty() && host_matches(&o.host, host))
+        .map(|o| o.browser.as_str())
+        .or(settings.browser.as_deref())
+        .filter(|command| !command.trim().is_empty())
+}
+
+fn launch(command: Option<&str>, url: &Url) {
+    let result = match command {
+        None => open::that(url.as_str()).map_err(|e| e.to_string()),
+        Some(command) => spawn(command, url),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to open {url}: {err}");
+    }
+}
+
+fn spawn(command: &str, url: &Url) -> Result<(), String> {
+    let args = command_line(command, url.as_str())?;
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .spawn()
+        .map_err(|e| format!("could not run '{}': {e}", args[0]))?;
+    // Reap the browser process once it exits
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Splits a browser command into arguments and puts the URL in place of `%u`,
+/// or at the end if the command has no `%u`.
+fn command_line(command: &str, url: &str) -> Result<Vec<String>, String> {
+    let mut args =
+        shlex::split(command).ok_or_else(|| format!("invalid browser command '{command}'"))?;
+    if args.is_empty() {
+        return Err("the browser command is empty".to_string());
+    }
+
+    if args.iter().any(|arg| arg.contains("%u")) {
+        for arg in &mut args {
+            *arg = arg.replace("%u", url);
+        }
+    } else {
+        args.push(url.to_string());
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_replaces_the_placeholder() {
+        assert_eq!(
+            command_line(
+                "firefox --private-window %u",
+                "https://example.com/?a=1&b=2"
+            )
+            .unwrap(),
+            [
+                "firefox",
+                "--private-window",
+                "https://example.com/?a=1&b=2"
+            ]
+        );
+        assert_eq!(
+            command_line("'/opt/My Browser/browser' --new-tab", "https://example.com").unwrap(),
+            [
+                "/opt/My Browser/browser",
+                "--new-tab",
+                "https://example.com"
+            ]
+        );
+        assert!(command_line("firefox 'unterminated", "https://example.com").is_err());
+        assert!(command_line("  ", "https://example.com").is_err());
+    }
+
+    #[test]
+    fn overrides_win_over_the_default_browser() {
+        let settings = Settings {
+            browser: Some("firefox %u".to_string()),
+            browser_overrides: vec![BrowserOverride {
+                host: ".github.com".to_string(),
+                browser: "chromium --profile-directory=Work %u".to_string(),
+            }],
+            ..Settings::default()
+        };
+        let browser = |raw: &str| browser_for(&settings, &Url::parse(raw).unwrap());
+
+        assert_eq!(
+            browser("https://gist.github.com/x"),
+            Some("chromium --profile-directory=Work %u")
+        );
+        assert_eq!(browser("https://example.com/"), Some("firefox %u"));
+        assert_eq!(
+            browser_for(
+                &Settings::default(),
+                &Url::parse("https://example.com/").unwrap()
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::thread;
+use url::Url;
+
+/// Opens `path` with its default application.
+pub fn open_file(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err(format!("{} no longer exists", path.display()));
+    }
+    open::that_detached(path).map_err(|e| e.to_string())
+}
+
+/// Opens the folder containing `path` in the file manager, with the file selected
+/// when the file manager supports `org.freedesktop.FileManager1`.
+pub fn show_in_folder(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err(format!("{} no longer exists", path.display()));
+    }
+    let uri = Url::from_file_path(path)
+        .map_err(|_| format!("{} is not an absolute path", path.display()))?;
+    let folder = path.parent().unwrap_or(path).to_path_buf();
+
+    // The D-Bus call waits for the file manager, which can take a moment to start
+    thread::spawn(move || {
+        if let Err(err) = show_items(uri.as_str()) {
+            eprintln!("File manager could not select the file ({err}), opening the folder");
+            if let Err(err) = open::that(&folder) {
+                eprintln!("Failed to open {}: {err}", folder.display());
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn show_items(uri: &str) -> zbus::Result<()> {
+    let connection = zbus::blocking::Connection::session()?;
+    connection.call_method(
+        Some("org.freedesktop.FileManager1"),
+        "/org/freedesktop/FileManager1",
+        Some("org.freedesktop.FileManager1"),
+        "ShowItems",
+        // The second argument is a startup notification id, which we do not have
+        &(vec![uri], ""),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn show_items(_uri: &str) -> Result<(), &'static str> {
+    Err("not supported here")
+}
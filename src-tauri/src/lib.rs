@@ -1,21 +1,51 @@
+mod browsing_data;
+mod cli;
+mod download_notifications;
+mod downloads;
+mod external;
+mod file_manager;
+mod link_cleaner;
+mod profile;
 mod settings;
-
-use open::that as open_in_browser;
+mod settings_store;
+mod settings_watcher;
+mod sign_in;
+#[cfg(unix)]
+mod single_instance;
+mod tray;
+mod url_policy;
+mod window_state;
+
+use browsing_data::DataKind;
+use downloads::{DownloadRegistry, DownloadStatus};
+use profile::Profile;
 use settings::Settings;
+use settings_store::SettingsStore;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tauri::{
-    menu::{Menu, MenuItem},
-    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    webview::DownloadEvent,
-    App, AppHandle, Manager, Theme, WebviewUrl, WebviewWindow, WebviewWindowBuilder,
+    webview::{DownloadEvent, PageLoadEvent},
+    App, AppHandle, Emitter, Manager, State, Theme, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder,
 };
 use tauri_plugin_notification::NotificationExt;
 use url::Url;
+use url_policy::{UrlAction, UrlPolicy};
 
 const CHATGPT_URL: &str = "https://chatgpt.com";
 
+/// Label of the download manager window.
+const DOWNLOADS_WINDOW: &str = "downloads";
+
+/// Emitted whenever the download history changes, so the download manager can refresh.
+const DOWNLOADS_CHANGED_EVENT: &str = "downloads-changed";
+
+/// Download history file, kept with the profile's webview data.
+const DOWNLOAD_HISTORY_FILE: &str = "download_history.json";
+
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+
 const INIT_SCRIPT: &str = r#"
 (function() {
     // Performance: Preconnect to CDN domains
@@ -56,6 +86,8 @@ const INIT_SCRIPT: &str = r#"
         }
     }, true);
 
+    // URL policy, generated from UrlPolicy in url_policy.rs
+__URL_POLICY__
     // External link handler
     document.addEventListener('click', function(e) {
         const link = e.target.closest('a');
@@ -66,24 +98,18 @@ const INIT_SCRIPT: &str = r#"
 
         try {
             const url = new URL(href);
-            const currentOrigin = window.location.origin;
-
-            const allowedDomains = [
-                'chatgpt.com',
-                'chat.openai.com',
-                'openai.com',
-                'oaistatic.com',
-                'oaiusercontent.com'
-            ];
-
-            const isAllowed = allowedDomains.some(domain =>
-                url.hostname === domain || url.hostname.endsWith('.' + domain)
-            );
-
-            if (!isAllowed && url.origin !== currentOrigin) {
-                e.preventDefault();
-                e.stopPropagation();
+            // Links within the page's own origin never leave the app
+            if (url.origin !== 'null' && url.origin === window.location.origin) return;
+
+            const decision = urlDecision(url);
+            if (decision === 'allow-in-app') return;
+
+            e.preventDefault();
+            e.stopPropagation();
+            if (decision === 'open-externally') {
                 window.open(href, '_blank');
+            } else {
+                console.log('Blocked link to ' + href);
             }
         } catch (err) {
             console.log(err)
@@ -92,6 +118,11 @@ const INIT_SCRIPT: &str = r#"
 })();
 "#;
 
+/// The injected script with the URL policy filled in.
+fn init_script(policy: &UrlPolicy) -> String {
+    INIT_SCRIPT.replace("__URL_POLICY__", &policy.script())
+}
+
 #[tauri::command]
 fn reload_webview(window: WebviewWindow) -> Result<(), String> {
     window
@@ -100,66 +131,178 @@ fn reload_webview(window: WebviewWindow) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn get_settings<R: tauri::Runtime>(app: AppHandle<R>) -> Settings {
-    Settings::load(&app)
+fn clear_cache(app: AppHandle) -> Result<(), String> {
+    clear_browsing_data(&app, DataKind::Cache)
+}
+
+#[tauri::command]
+fn clear_cookies(app: AppHandle) -> Result<(), String> {
+    clear_browsing_data(&app, DataKind::Session)
 }
 
 #[tauri::command]
-fn save_settings<R: tauri::Runtime>(app: AppHandle<R>, settings: Settings) -> Result<(), String> {
-    settings.save(&app)?;
+fn clear_all_data(app: AppHandle) -> Result<(), String> {
+    clear_browsing_data(&app, DataKind::All)
+}
 
-    // Apply decorations setting
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.set_decorations(!settings.hide_decorations);
+/// Bytes used by the webview data folder and the HTTP cache.
+#[tauri::command]
+fn get_data_size(app: AppHandle) -> u64 {
+    browsing_data::total_size(&app)
+}
+
+/// The download history, newest first, optionally only entries matching `query`.
+#[tauri::command]
+fn get_downloads(
+    registry: State<'_, DownloadRegistry>,
+    query: Option<String>,
+) -> Vec<downloads::Download> {
+    registry.search(query.as_deref().unwrap_or_default())
+}
+
+#[tauri::command]
+fn open_download(registry: State<'_, DownloadRegistry>, id: u64) -> Result<(), String> {
+    let download = registry.get(id).ok_or("No such download")?;
+    file_manager::open_file(&download.destination)
+}
+
+#[tauri::command]
+fn show_download_in_folder(registry: State<'_, DownloadRegistry>, id: u64) -> Result<(), String> {
+    let download = registry.get(id).ok_or("No such download")?;
+    file_manager::show_in_folder(&download.destination)
+}
+
+/// Downloads the address of a history entry again, as a new entry.
+#[tauri::command]
+fn retry_download(app: AppHandle, id: u64) -> Result<(), String> {
+    let download = app
+        .state::<DownloadRegistry>()
+        .get(id)
+        .ok_or("No such download")?;
+    redownload(&app, &download)
+}
+
+/// Removes a history entry, and the downloaded file too when `delete_file` is set.
+#[tauri::command]
+fn delete_download(app: AppHandle, id: u64, delete_file: bool) -> Result<(), String> {
+    let registry = app.state::<DownloadRegistry>();
+    let download = registry.get(id).ok_or("No such download")?;
+    if delete_file && download.status == DownloadStatus::Completed {
+        if let Err(err) = fs::remove_file(&download.destination) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(format!(
+                    "Failed to delete {}: {err}",
+                    download.destination.display()
+                ));
+            }
+        }
     }
+    registry.remove(id);
+    let _ = app.emit(DOWNLOADS_CHANGED_EVENT, ());
+    Ok(())
+}
 
+#[tauri::command]
+fn open_downloads_window(app: AppHandle) -> Result<(), String> {
+    show_downloads_window(&app).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_settings(store: State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+#[tauri::command]
+fn save_settings(store: State<'_, SettingsStore>, settings: Settings) -> Result<(), String> {
+    store.update(|current| *current = settings)?;
     Ok(())
 }
 
 #[tauri::command]
-fn toggle_notifications<R: tauri::Runtime>(app: AppHandle<R>) -> Result<bool, String> {
-    let mut settings = Settings::load(&app);
-    settings.notifications_enabled = !settings.notifications_enabled;
-    settings.save(&app)?;
-    Ok(settings.notifications_enabled)
+fn toggle_notifications(store: State<'_, SettingsStore>) -> Result<bool, String> {
+    store
+        .update(|s| s.notifications_enabled = !s.notifications_enabled)
+        .map(|s| s.notifications_enabled)
 }
 
 #[tauri::command]
-fn toggle_decorations<R: tauri::Runtime>(app: AppHandle<R>) -> Result<bool, String> {
-    let mut settings = Settings::load(&app);
-    settings.hide_decorations = !settings.hide_decorations;
-    settings.save(&app)?;
+fn toggle_decorations(store: State<'_, SettingsStore>) -> Result<bool, String> {
+    store
+        .update(|s| s.hide_decorations = !s.hide_decorations)
+        .map(|s| s.hide_decorations)
+}
 
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.set_decorations(!settings.hide_decorations);
+#[tauri::command]
+fn toggle_close_to_tray(store: State<'_, SettingsStore>) -> Result<bool, String> {
+    if !store.get().show_tray {
+        return Err("Close to tray needs the tray icon, enable show_tray first".to_string());
     }
-
-    Ok(settings.hide_decorations)
+    store
+        .update(|s| s.close_to_tray = !s.close_to_tray)
+        .map(|s| s.close_to_tray)
 }
 
 #[tauri::command]
-fn toggle_close_to_tray<R: tauri::Runtime>(app: AppHandle<R>) -> Result<bool, String> {
-    let mut settings = Settings::load(&app);
-    settings.close_to_tray = !settings.close_to_tray;
-    settings.save(&app)?;
-    Ok(settings.close_to_tray)
+fn toggle_tray(store: State<'_, SettingsStore>) -> Result<bool, String> {
+    store
+        .update(|s| s.show_tray = !s.show_tray)
+        .map(|s| s.show_tray)
 }
 
 #[tauri::command]
-fn toggle_tray_icon<R: tauri::Runtime>(app: AppHandle<R>) -> Result<bool, String> {
-    let mut settings = Settings::load(&app);
-    settings.tray_icon_light = !settings.tray_icon_light;
-    eprintln!("Toggling tray icon to: {}", settings.tray_icon_light);
-    settings.save(&app)?;
-    Ok(settings.tray_icon_light)
+fn toggle_tray_icon(store: State<'_, SettingsStore>) -> Result<bool, String> {
+    store
+        .update(|s| s.tray_icon_light = !s.tray_icon_light)
+        .map(|s| s.tray_icon_light)
 }
 
+/// Text handed over on the command line, typed into the composer once the page loads.
+struct PendingPrompt(Mutex<Option<String>>);
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    let launch = match cli::parse(raw_args.clone()) {
+        Ok(cli::Command::Launch(launch)) => launch,
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(cli::Command::Version) => {
+            println!("chatgpt-desktop {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("chatgpt-desktop: {err}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let incognito = launch.incognito;
+    let portable_root = profile::portable_root(launch.data_dir.as_deref());
+
+    // Incognito sessions are never shared, so they skip the single-instance check
+    #[cfg(unix)]
+    let instance_name = profile::instance_name(launch.profile.as_deref(), portable_root.as_deref());
+    #[cfg(unix)]
+    let instance_listener = if incognito {
+        None
+    } else {
+        match single_instance::acquire(&instance_name, &raw_args) {
+            Ok(single_instance::Instance::Primary(listener)) => Some(listener),
+            Ok(single_instance::Instance::Secondary) => return,
+            Err(err) => {
+                eprintln!("Single-instance check failed, starting anyway: {err}");
+                None
+            }
+        }
+    };
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             reload_webview,
             get_settings,
@@ -167,368 +310,525 @@ pub fn run() {
             toggle_notifications,
             toggle_decorations,
             toggle_close_to_tray,
-            toggle_tray_icon
+            toggle_tray,
+            toggle_tray_icon,
+            clear_cache,
+            clear_cookies,
+            clear_all_data,
+            get_data_size,
+            get_downloads,
+            open_download,
+            show_download_in_folder,
+            retry_download,
+            delete_download,
+            open_downloads_window
         ])
-        .setup(|app| {
+        .setup(move |app| {
             if app.get_webview_window("main").is_none() {
-                initialize_application(app)?;
+                initialize_application(app, launch, portable_root)?;
+            }
+
+            #[cfg(unix)]
+            if let Some(listener) = instance_listener {
+                let handle = app.handle().clone();
+                single_instance::listen(listener, move |args| match cli::parse(args) {
+                    Ok(cli::Command::Launch(launch)) => handle_launch(&handle, launch),
+                    Ok(_) => {}
+                    Err(err) => eprintln!("Ignoring launch request: {err}"),
+                });
             }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(move |_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if incognito {
+                    profile::remove_incognito_dir();
+                } else {
+                    #[cfg(unix)]
+                    single_instance::release(&instance_name);
+                }
+            }
+        });
 }
 
 /// Prepares configuration and window so the app feels desktop-native.
-fn initialize_application<R: tauri::Runtime>(app: &mut App<R>) -> tauri::Result<()> {
-    let settings = Settings::load(&app.handle());
-    let (_decorations, _window) = init_main_window(app, settings.hide_decorations)?;
-    setup_tray(app)?;
+fn initialize_application<R: tauri::Runtime>(
+    app: &mut App<R>,
+    launch: cli::LaunchArgs,
+    portable_root: Option<PathBuf>,
+) -> tauri::Result<()> {
+    app.manage(Profile::new(
+        app.handle(),
+        launch.profile.as_deref(),
+        launch.incognito,
+        portable_root,
+    ));
+    if launch.incognito {
+        profile::prune_incognito_dirs();
+        // Without its own folder the session would write into the default webview storage
+        profile::create_incognito_dir()?;
+    }
+
+    let path = Settings::get_settings_path(app.handle());
+    let settings = Settings::load_from(&path).unwrap_or_else(|err| {
+        report_settings_error(app.handle(), &err);
+        Settings::default()
+    });
+    app.manage(SettingsStore::new(path, settings.clone()));
+    browsing_data::prune_http_cache(app.handle(), settings.cache_limit_mb);
+    subscribe_to_settings(app.handle());
+
+    // Starting hidden is only safe when the tray can bring the window back
+    let hidden = launch.hidden && settings.show_tray;
+    if launch.hidden && !hidden {
+        eprintln!("Ignoring --hidden because the tray icon is disabled");
+    }
+    app.manage(PendingPrompt(Mutex::new(launch.prompt.clone())));
+    let history = app
+        .state::<Profile>()
+        .data_dir()
+        .join(DOWNLOAD_HISTORY_FILE);
+    app.manage(DownloadRegistry::load(history));
+    app.manage(tray::DataSize::start(app.handle()));
+    #[cfg(target_os = "linux")]
+    app.manage(download_notifications::DownloadActions::default());
+
+    let url = launch.target_url(&service_url(&settings), &start_url(&settings));
+    let (_decorations, _window) = init_main_window(app, settings.hide_decorations, url, !hidden)?;
+    if settings.show_tray {
+        tray::create(app.handle())?;
+    }
+
+    if let Err(err) = settings_watcher::watch(app.handle()) {
+        eprintln!("Failed to watch settings file: {err}");
+    }
     Ok(())
 }
 
-fn load_tray_icon<R: tauri::Runtime>(
-    app: &AppHandle<R>,
-    use_light: bool,
-) -> tauri::image::Image<'static> {
-    if use_light {
-        let icon_name = "icon-light-32x32.png";
-
-        let search_paths = vec![
-            app.path()
-                .resource_dir()
-                .ok()
-                .map(|d| d.join("icons").join(icon_name)),
-            std::env::current_exe()
-                .ok()
-                .and_then(|exe| exe.parent().map(|p| p.join("icons").join(icon_name))),
-            std::env::current_dir()
-                .ok()
-                .map(|d| d.join("src-tauri").join("icons").join(icon_name)),
-        ];
-
-        for path_opt in search_paths {
-            if let Some(icon_path) = path_opt {
-                if let Ok(img_data) = std::fs::read(&icon_path) {
-                    if let Ok(img) = image::load_from_memory(&img_data) {
-                        let rgba = img.to_rgba8();
-                        let (width, height) = rgba.dimensions();
-                        eprintln!("✓ Loaded tray icon from: {}", icon_path.display());
-                        return tauri::image::Image::new_owned(rgba.into_raw(), width, height);
-                    }
+/// Wires the window, the tray and the webview to settings changes.
+fn subscribe_to_settings<R: tauri::Runtime>(app: &AppHandle<R>) {
+    let store = app.state::<SettingsStore>();
+
+    let handle = app.clone();
+    store.subscribe(move |old, new| {
+        if old.hide_decorations != new.hide_decorations {
+            if let Some(window) = handle.get_webview_window("main") {
+                let _ = window.set_decorations(!new.hide_decorations);
+            }
+        }
+    });
+
+    let handle = app.clone();
+    store.subscribe(move |old, new| {
+        if old.show_tray != new.show_tray {
+            if new.show_tray {
+                if let Err(err) = tray::create(&handle) {
+                    eprintln!("Failed to create tray icon: {err}");
+                }
+            } else {
+                tray::remove(&handle);
+                // A window hidden to the tray would have no way back
+                let hidden = handle
+                    .get_webview_window("main")
+                    .is_some_and(|window| !window.is_visible().unwrap_or(true));
+                if hidden {
+                    show_main_window(&handle);
                 }
             }
         }
-    }
+        tray::refresh(&handle, new);
+    });
 
-    if let Some(default_icon) = app.default_window_icon() {
-        let rgba = default_icon.rgba().to_vec();
-        tauri::image::Image::new_owned(rgba, default_icon.width(), default_icon.height())
-    } else {
-        tauri::image::Image::new_owned(vec![0, 0, 0, 0], 1, 1)
-    }
+    let handle = app.clone();
+    store.subscribe(move |old, new| {
+        if old.url_rules != new.url_rules {
+            if let Some(window) = handle.get_webview_window("main") {
+                let _ = window.eval(url_policy(new).update_script());
+            }
+        }
+    });
+
+    let handle = app.clone();
+    store.subscribe(move |_old, new| {
+        let _ = handle.emit("settings-changed", new);
+    });
 }
 
-fn update_tray_menu<R: tauri::Runtime>(app: &AppHandle<R>) {
-    let settings = Settings::load(app);
+/// Handles a launch forwarded by a second process.
+fn handle_launch<R: tauri::Runtime>(app: &AppHandle<R>, launch: cli::LaunchArgs) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
 
-    if let Some(tray) = app.tray_by_id("main") {
-        let icon = load_tray_icon(app, settings.tray_icon_light);
-        let _ = tray.set_icon(Some(icon));
+    if launch.opens_page() {
+        let settings = app.state::<SettingsStore>().get();
+        *app.state::<PendingPrompt>().0.lock().unwrap() = launch.prompt.clone();
+        let _ = window.navigate(launch.target_url(&service_url(&settings), &start_url(&settings)));
+    }
+    if !launch.hidden {
+        show_main_window(app);
+    }
+}
 
-        let mut tooltip_parts = vec!["ChatGPT Desktop"];
-        if settings.close_to_tray {
-            tooltip_parts.push("(Close to Tray)");
+/// Removes `kind` from the main webview. Signing out reloads the page so it
+/// shows the login screen, and the tray is refreshed with the new data size.
+fn clear_browsing_data<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    kind: DataKind,
+) -> Result<(), String> {
+    let window = app
+        .get_webview_window("main")
+        .ok_or("Main window not found")?;
+
+    let handle = app.clone();
+    let reload = window.clone();
+    browsing_data::clear(&window, kind, move |result| {
+        match result {
+            Ok(()) => {
+                eprintln!("Cleared {}", kind.describe());
+                if kind != DataKind::Cache {
+                    let _ = reload.eval("window.location.reload();");
+                }
+            }
+            Err(err) => eprintln!("Failed to clear {}: {err}", kind.describe()),
         }
+        tray::refresh(&handle, &handle.state::<SettingsStore>().get());
+    })
+}
+
+/// The built-in allowlist extended with the user's `url_rules` and `identity_providers`.
+fn url_policy(settings: &Settings) -> UrlPolicy {
+    UrlPolicy::new(
+        settings.url_rules.clone(),
+        settings.identity_providers.clone(),
+    )
+}
 
-        if !settings.notifications_enabled {
-            tooltip_parts.push("(Notifications Off)");
+/// Applies the URL policy to a navigation in the main webview.
+///
+/// Every main-frame navigation gets the full policy, whether it comes from a
+/// click, a script or a redirect. Frames, such as the Cloudflare challenge and
+/// Stripe checkout, stay where the page put them unless a rule blocks them.
+fn handle_main_navigation<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    url: &Url,
+    main_frame: bool,
+) -> bool {
+    let policy = url_policy(&app.state::<SettingsStore>().get());
+    match policy.decide(url) {
+        UrlAction::AllowInApp => true,
+        UrlAction::Block => {
+            eprintln!("Blocked navigation to {url}");
+            false
+        }
+        // A provider page reached from the ChatGPT login
+        UrlAction::OpenExternally if policy.is_sign_in(url) => true,
+        UrlAction::OpenExternally if !main_frame => true,
+        UrlAction::OpenExternally => {
+            eprintln!("Opening navigation to {url} in the browser");
+            external::open(app, url);
+            false
         }
+    }
+}
+
+/// Applies the URL policy to the main frame of the main webview on Linux.
+///
+/// WebKitGTK reports frame navigations through the same callback as page
+/// navigations without saying which frame they are for, so `on_navigation` has
+/// to treat them all as frames. The response tells them apart, so main-frame
+/// pages are judged here once it arrives, after any redirects. This handler
+/// only looks at responses, which wry's navigation handler leaves alone.
+#[cfg(target_os = "linux")]
+fn watch_main_frame<R: tauri::Runtime>(window: &WebviewWindow<R>) -> tauri::Result<()> {
+    use webkit2gtk::glib::Cast;
+    use webkit2gtk::{
+        PolicyDecisionExt, PolicyDecisionType, ResponsePolicyDecision, ResponsePolicyDecisionExt,
+        URIRequestExt, WebViewExt,
+    };
+
+    let app = window.app_handle().clone();
+    window.with_webview(move |webview| {
+        webview
+            .inner()
+            .connect_decide_policy(move |_, decision, kind| {
+                if kind != PolicyDecisionType::Response {
+                    return false;
+                }
+                let Some(response) = decision.downcast_ref::<ResponsePolicyDecision>() else {
+                    return false;
+                };
+                if !response.is_main_frame_main_resource() {
+                    return false;
+                }
+                let Some(url) = response
+                    .request()
+                    .and_then(|request| request.uri())
+                    .and_then(|uri| Url::parse(&uri).ok())
+                else {
+                    return false;
+                };
 
-        let _ = tray.set_tooltip(Some(tooltip_parts.join(" ")));
+                // Allowed pages keep WebKit's own handling, which also starts downloads
+                if handle_main_navigation(&app, &url, true) {
+                    return false;
+                }
+                decision.ignore();
+                true
+            });
+    })
+}
 
-        let show_hide = MenuItem::with_id(app, "show_hide", "Show/Hide", true, None::<&str>).ok();
-        let notifications = MenuItem::with_id(
-            app,
-            "toggle_notifications",
-            if settings.notifications_enabled {
-                "Disable Notifications"
-            } else {
-                "Enable Notifications"
-            },
-            true,
-            None::<&str>,
-        )
-        .ok();
-
-        let decorations = MenuItem::with_id(
-            app,
-            "toggle_decorations",
-            if settings.hide_decorations {
-                "Show Window Decorations"
-            } else {
-                "Hide Window Decorations"
-            },
-            true,
-            None::<&str>,
-        )
-        .ok();
-
-        let close_to_tray = MenuItem::with_id(
-            app,
-            "toggle_close_to_tray",
-            if settings.close_to_tray {
-                "✓ Close to Tray"
-            } else {
-                "Close to Tray"
-            },
-            true,
-            None::<&str>,
-        )
-        .ok();
-
-        let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>).ok();
-
-        if let (Some(sh), Some(n), Some(d), Some(ct), Some(q)) =
-            (show_hide, notifications, decorations, close_to_tray, quit)
-        {
-            if let Ok(menu) = Menu::with_items(app, &[&sh, &n, &d, &ct, &q]) {
-                let _ = tray.set_menu(Some(menu));
-            }
+/// The configured ChatGPT base address, or the default one if it is unusable.
+fn service_url(settings: &Settings) -> Url {
+    parse_allowed_url("service_url", &settings.service_url).unwrap_or_else(|| {
+        CHATGPT_URL
+            .parse()
+            .expect("the chatgpt url constant should always be valid")
+    })
+}
+
+/// The page to open on launch, falling back to the service address.
+fn start_url(settings: &Settings) -> Url {
+    settings
+        .start_url
+        .as_deref()
+        .and_then(|raw| parse_allowed_url("start_url", raw))
+        .unwrap_or_else(|| service_url(settings))
+}
+
+fn parse_allowed_url(setting: &str, raw: &str) -> Option<Url> {
+    match Url::parse(raw) {
+        Ok(url) if UrlPolicy::default().allows_page(&url) => Some(url),
+        Ok(url) => {
+            eprintln!("Ignoring {setting}: {url} is not a ChatGPT URL");
+            None
+        }
+        Err(err) => {
+            eprintln!("Ignoring {setting}: invalid URL '{raw}': {err}");
+            None
         }
     }
 }
 
-fn setup_tray<R: tauri::Runtime>(app: &App<R>) -> tauri::Result<()> {
-    let settings = Settings::load(&app.handle());
+/// Builds a script that waits for the message box and types `prompt` into it.
+fn prompt_fill_script(prompt: &str) -> String {
+    let prompt = serde_json::to_string(prompt).unwrap_or_default();
+    format!(
+        r#"
+(function() {{
+    const prompt = {prompt};
+    const started = Date.now();
+    const timer = setInterval(function() {{
+        const composer = document.querySelector('#prompt-textarea');
+        if (composer) {{
+            clearInterval(timer);
+            composer.focus();
+            document.execCommand('insertText', false, prompt);
+        }} else if (Date.now() - started > 30000) {{
+            clearInterval(timer);
+        }}
+    }}, 250);
+}})();
+"#
+    )
+}
 
-    let show_hide = MenuItem::with_id(app, "show_hide", "Show/Hide", true, None::<&str>)?;
-    let notifications = MenuItem::with_id(
-        app,
-        "toggle_notifications",
-        if settings.notifications_enabled {
-            "Disable Notifications"
-        } else {
-            "Enable Notifications"
-        },
-        true,
-        None::<&str>,
-    )?;
-    let decorations = MenuItem::with_id(
-        app,
-        "toggle_decorations",
-        if settings.hide_decorations {
-            "Show Window Decorations"
-        } else {
-            "Hide Window Decorations"
-        },
-        true,
-        None::<&str>,
-    )?;
-    let close_to_tray = MenuItem::with_id(
-        app,
-        "toggle_close_to_tray",
-        if settings.close_to_tray {
-            "✓ Close to Tray"
-        } else {
-            "Close to Tray"
-        },
-        true,
-        None::<&str>,
-    )?;
-    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-
-    let menu = Menu::with_items(
-        app,
-        &[
-            &show_hide,
-            &notifications,
-            &decorations,
-            &close_to_tray,
-            &quit,
-        ],
-    )?;
-
-    // Build tooltip based on settings
-    let mut tooltip_parts = vec!["ChatGPT Desktop"];
-    if settings.close_to_tray {
-        tooltip_parts.push("(Close to Tray)");
+/// Brings the main window to the front, restoring it from the tray or minimized state.
+fn show_main_window<R: tauri::Runtime>(app: &AppHandle<R>) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
     }
-    if !settings.notifications_enabled {
-        tooltip_parts.push("(Notifications Off)");
+}
+
+/// Starts downloading the address of `download` again in the main window.
+fn redownload<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    download: &downloads::Download,
+) -> Result<(), String> {
+    if !download.can_retry() {
+        return Err("This file was created by the page and cannot be downloaded again".to_string());
     }
+    let window = app
+        .get_webview_window("main")
+        .ok_or("Main window not found")?;
+    downloads::start_download(&window, &download.url)
+}
 
-    let icon = load_tray_icon(&app.handle(), settings.tray_icon_light);
-
-    let _tray = TrayIconBuilder::new()
-        .icon(icon)
-        .tooltip(tooltip_parts.join(" "))
-        .menu(&menu)
-        .on_menu_event(move |app, event| match event.id.as_ref() {
-            "show_hide" => {
-                if let Some(window) = app.get_webview_window("main") {
-                    if window.is_visible().unwrap_or(false) {
-                        let _ = window.hide();
-                    } else {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                    }
-                }
-            }
-            "toggle_notifications" => {
-                let _ = toggle_notifications(app.clone());
-                update_tray_menu(&app);
-            }
-            "toggle_decorations" => {
-                let _ = toggle_decorations(app.clone());
-                update_tray_menu(&app);
-            }
-            "toggle_close_to_tray" => {
-                let _ = toggle_close_to_tray(app.clone());
-                update_tray_menu(&app);
-            }
-            "quit" => {
-                app.exit(0);
-            }
-            _ => {}
-        })
-        .on_tray_icon_event(|tray, event| {
-            if let TrayIconEvent::Click {
-                button: MouseButton::Left,
-                button_state: MouseButtonState::Up,
-                ..
-            } = event
-            {
-                let app = tray.app_handle();
-                if let Some(window) = app.get_webview_window("main") {
-                    if window.is_visible().unwrap_or(false) {
-                        let _ = window.hide();
-                    } else {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                    }
-                }
-            }
-        })
-        .build(app)?;
+/// Shows the download manager, creating it on first use.
+fn show_downloads_window<R: tauri::Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
+    if let Some(window) = app.get_webview_window(DOWNLOADS_WINDOW) {
+        window.unminimize()?;
+        window.show()?;
+        return window.set_focus();
+    }
 
+    WebviewWindowBuilder::new(
+        app,
+        DOWNLOADS_WINDOW,
+        WebviewUrl::App("downloads.html".into()),
+    )
+    .title("Downloads")
+    .inner_size(640.0, 480.0)
+    .min_inner_size(420.0, 280.0)
+    .build()?;
     Ok(())
 }
 
-/// Handles download events: saves to Downloads folder and notifies user.
+/// Logs a settings load failure and tells the user where the file is broken.
+fn report_settings_error<R: tauri::Runtime>(app: &AppHandle<R>, err: &str) {
+    eprintln!("{err}");
+    let _ = app
+        .notification()
+        .builder()
+        .title("Settings file could not be loaded")
+        .body(format!("{err}\nUsing defaults until it is fixed."))
+        .show();
+}
+
+/// Handles download events: saves to the folder picked by the settings and notifies user.
 fn create_download_handler<R: tauri::Runtime>(
     app_handle: AppHandle<R>,
 ) -> impl Fn(tauri::Webview<R>, DownloadEvent) -> bool {
-    let download_path = Arc::new(Mutex::new(Option::<PathBuf>::None));
-
-    move |_webview, event| {
-        match event {
-            DownloadEvent::Requested { destination, .. } => {
-                // Get downloads directory
-                let download_dir = match app_handle.path().download_dir() {
-                    Ok(dir) => dir,
-                    Err(_) => return false,
-                };
-
-                // Set destination to downloads folder
-                let final_path = download_dir.join(&destination);
-                let mut locked_path = download_path.lock().unwrap();
-                *locked_path = Some(final_path.clone());
-                *destination = final_path;
-
-                // Show notification if enabled
-                let app = app_handle.clone();
-                let filename = destination
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("file")
-                    .to_string();
-
-                tauri::async_runtime::spawn(async move {
-                    let settings = Settings::load(&app);
-                    if settings.notifications_enabled {
-                        let _ = app
-                            .notification()
-                            .builder()
-                            .title("Downloading file")
-                            .body(&format!("Saving: {}", filename))
-                            .show();
-                    }
-                });
-
-                return true;
+    move |_webview, mut event| {
+        let requested = matches!(event, DownloadEvent::Requested { .. });
+
+        // Each download is tracked on its own so overlapping ones report the right file
+        let registry = app_handle.state::<DownloadRegistry>();
+        let destination_for = |filename: &str| {
+            let dir = download_folder(&app_handle, filename)?;
+            let path = registry.reserve(&dir, filename);
+            if app_handle
+                .state::<SettingsStore>()
+                .get()
+                .ask_download_location
+            {
+                // Other downloads can arrive while the dialog is open, so the
+                // proposed name stays reserved until it closes
+                let chosen = downloads::ask_save_path(&app_handle, &path);
+                registry.release(&path);
+                chosen
+            } else {
+                Some(path)
             }
-            DownloadEvent::Finished { success, .. } => {
-                let path_opt = download_path.lock().unwrap().clone();
-
-                if let Some(final_path) = path_opt {
-                    let app = app_handle.clone();
-                    tauri::async_runtime::spawn(async move {
-                        let settings = Settings::load(&app);
-                        if settings.notifications_enabled {
-                            if success {
-                                let _ = app
-                                    .notification()
-                                    .builder()
-                                    .title("Download completed")
-                                    .body(&format!("Saved to: {}", final_path.display()))
-                                    .show();
-                            } else {
-                                let _ = app
-                                    .notification()
-                                    .builder()
-                                    .title("Download failed")
-                                    .body("Could not complete the download")
-                                    .show();
-                            }
-                        }
-                    });
-                }
-                return true;
+        };
+        match registry.record(&mut event, destination_for) {
+            Some(download) => {
+                let _ = app_handle.emit(DOWNLOADS_CHANGED_EVENT, ());
+                download_notifications::notify(&app_handle, download);
             }
-            _ => {}
+            // No folder to save to, or the save dialog was cancelled
+            None if requested => return false,
+            None => {}
         }
         true
     }
 }
 
+/// Creates and returns the folder a download named `filename` is saved to.
+///
+/// Falls back to the Downloads folder when the configured one cannot be created.
+fn download_folder<R: tauri::Runtime>(app: &AppHandle<R>, filename: &str) -> Option<PathBuf> {
+    let default_dir = app.path().download_dir().ok()?;
+    let home = app.path().home_dir().ok()?;
+    let dir = downloads::folder_for(
+        &app.state::<SettingsStore>().get(),
+        filename,
+        &home,
+        &default_dir,
+    );
+
+    match fs::create_dir_all(&dir) {
+        Ok(()) => Some(dir),
+        Err(err) => {
+            eprintln!("Failed to create download folder {}: {err}", dir.display());
+            Some(default_dir)
+        }
+    }
+}
+
 /// Creates the main webview window and applies the decoration state.
 fn init_main_window<R: tauri::Runtime>(
     app: &App<R>,
     hide_decorations: bool,
+    url: Url,
+    visible: bool,
 ) -> tauri::Result<(Arc<Mutex<bool>>, WebviewWindow<R>)> {
     let decorations = Arc::new(Mutex::new(!hide_decorations));
     let cache_dir = prepare_webview_cache(app);
+    let incognito = app.state::<Profile>().is_incognito();
+    if incognito && cache_dir.is_none() {
+        // Falling back to the default storage would keep the session on disk
+        return Err(std::io::Error::other("the incognito data folder is missing").into());
+    }
+    let policy = url_policy(&app.state::<SettingsStore>().get());
+    let policy_app = app.handle().clone();
+    let navigation_app = app.handle().clone();
 
     let mut webview_builder = WebviewWindowBuilder::new(
         app,
         "main",
-        WebviewUrl::External(
-            CHATGPT_URL
-                .parse()
-                .expect("the chatgpt url constant should always be valid"),
-        ),
+        WebviewUrl::External(url),
     )
-    .title("ChatGPT Desktop")
+    .title(if incognito {
+        "ChatGPT Desktop (Incognito)"
+    } else {
+        "ChatGPT Desktop"
+    })
     .theme(Some(Theme::Dark))
     .inner_size(1200.0, 800.0)
     .min_inner_size(400.0, 300.0)
-    .visible(true)
-    .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36")
+    // Shown once the saved geometry has been applied, to avoid a visible jump
+    .visible(false)
+    .user_agent(USER_AGENT)
     .accept_first_mouse(true)
-    .initialization_script(INIT_SCRIPT)
+    .initialization_script(init_script(&policy))
     .additional_browser_args("--enable-features=WebRTCPipeWireCapturer,VaapiVideoDecodeLinuxGL --enable-gpu-rasterization --enable-zero-copy --disable-software-rasterizer --enable-accelerated-video-decode")
     .on_download(create_download_handler(app.handle().clone()))
-    .on_new_window(|url, _features| {
+    .on_page_load(|window, payload| {
+        if payload.event() != PageLoadEvent::Finished {
+            return;
+        }
+        // The injected copy of the policy dates from window creation
+        let settings = window.state::<SettingsStore>().get();
+        let _ = window.eval(url_policy(&settings).update_script());
+
+        let prompt = window.state::<PendingPrompt>().0.lock().unwrap().take();
+        if let Some(prompt) = prompt {
+            let _ = window.eval(prompt_fill_script(&prompt));
+        }
+    })
+    // Only WebKitGTK passes frame navigations here; see `watch_main_frame`
+    .on_navigation(move |url| {
+        handle_main_navigation(&navigation_app, url, cfg!(not(target_os = "linux")))
+    })
+    .on_new_window(move |url, features| {
         if url.scheme() == "blob" || url.scheme() == "data" {
             return tauri::webview::NewWindowResponse::Deny;
         }
 
-        if is_allowed_url(&url) {
-            tauri::webview::NewWindowResponse::Allow
-        } else {
-            let _ = open_in_browser(url.as_str());
-            tauri::webview::NewWindowResponse::Deny
+        let policy = url_policy(&policy_app.state::<SettingsStore>().get());
+        if policy.is_sign_in(&url) {
+            match sign_in::open_window(&policy_app, &url, features) {
+                Ok(window) => return tauri::webview::NewWindowResponse::Create { window },
+                Err(err) => eprintln!("Failed to open sign-in window for {url}: {err}"),
+            }
+        }
+
+        match policy.decide(&url) {
+            UrlAction::AllowInApp => tauri::webview::NewWindowResponse::Allow,
+            UrlAction::OpenExternally => {
+                external::open(&policy_app, &url);
+                tauri::webview::NewWindowResponse::Deny
+            }
+            UrlAction::Block => {
+                eprintln!("Blocked new window for {url}");
+                tauri::webview::NewWindowResponse::Deny
+            }
         }
     });
 
@@ -537,56 +837,56 @@ fn init_main_window<R: tauri::Runtime>(
     }
 
     let window = webview_builder.build()?;
+    #[cfg(target_os = "linux")]
+    watch_main_frame(&window)?;
 
     if hide_decorations {
         let _ = window.set_decorations(false);
     }
 
-    // Setup close to tray handler
+    let state_path = window_state::get_window_state_path(app.handle());
+    window_state::restore(&window, &state_path);
+    if visible {
+        window.show()?;
+    }
+    // An incognito window should not move the profile's regular window around
+    let save_state = (!incognito).then(|| window_state::track(&window, state_path.clone()));
+
+    // Setup geometry tracking and close to tray handler
     let app_handle = app.handle().clone();
-    window.on_window_event(move |event| {
-        if let tauri::WindowEvent::CloseRequested { api, .. } = event {
-            let settings = Settings::load(&app_handle);
-            if settings.close_to_tray {
+    let tracked_window = window.clone();
+    window.on_window_event(move |event| match event {
+        tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) => {
+            if let Some(save_state) = &save_state {
+                let _ = save_state.send(());
+            }
+        }
+        tauri::WindowEvent::CloseRequested { api, .. } => {
+            if save_state.is_some() {
+                window_state::save_now(&tracked_window, &state_path);
+            }
+
+            // Only hide when there is a tray icon to bring the window back with
+            let has_tray = app_handle.tray_by_id(tray::TRAY_ID).is_some();
+            if has_tray && app_handle.state::<SettingsStore>().get().close_to_tray {
                 api.prevent_close();
-                if let Some(window) = app_handle.get_webview_window("main") {
-                    let _ = window.hide();
-                }
+                let _ = tracked_window.hide();
             }
         }
+        _ => {}
     });
 
     Ok((decorations, window))
 }
 
-/// Ensures the webview cache directory exists and reports its path.
+/// Ensures the profile's webview cache directory exists and reports its path.
 fn prepare_webview_cache<R: tauri::Runtime>(app: &App<R>) -> Option<PathBuf> {
-    app.path().app_data_dir().ok().and_then(|dir| {
-        let cache_dir = dir.join("webview-cache");
-        match fs::create_dir_all(&cache_dir) {
-            Ok(_) => Some(cache_dir),
-            Err(err) => {
-                eprintln!("Failed to create webview cache directory: {err}");
-                None
-            }
+    let cache_dir = browsing_data::data_dir(app.handle());
+    match fs::create_dir_all(&cache_dir) {
+        Ok(_) => Some(cache_dir),
+        Err(err) => {
+            eprintln!("Failed to create webview cache directory: {err}");
+            None
         }
-    })
-}
-
-/// Restricts new webview windows to known ChatGPT hosts, otherwise opens in the browser.
-fn is_allowed_url(url: &Url) -> bool {
-    match url.scheme() {
-        "https" | "http" => match url.host_str() {
-            Some(host) => {
-                host == "chatgpt.com"
-                    || host == "chat.openai.com"
-                    || host.ends_with(".openai.com")
-                    || host.ends_with(".oaistatic.com")
-                    || host.ends_with(".oaiusercontent.com")
-            }
-            _ => true,
-        },
-        "about" | "data" | "blob" | "wss" | "ws" => true,
-        _ => false,
     }
 }
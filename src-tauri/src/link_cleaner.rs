@@ -0,0 +1,188 @@
+use crate::url_policy::{glob_matches, host_matches};
+use url::{form_urlencoded, Url};
+
+/// Default for the `tracking_parameters` setting. `*` matches any run of characters.
+pub const DEFAULT_TRACKING_PARAMETERS: &[&str] = &[
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "ttclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_hsenc",
+    "_hsmkt",
+    "mkt_tok",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+];
+
+/// Redirect wrappers as (host pattern, path, query parameter holding the real link).
+const REDIRECTS: &[(&str, &str, &str)] = &[
+    ("www.google.*", "/url", "q"),
+    ("www.google.*", "/url", "url"),
+    ("google.*", "/url", "q"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("l.instagram.com", "/", "u"),
+    ("www.youtube.com", "/redirect", "q"),
+    ("out.reddit.com", "*", "url"),
+    ("www.linkedin.com", "/safety/go", "url"),
+    ("t.umblr.com", "/redirect", "z"),
+    ("slack-redir.net", "/link", "url"),
+    ("steamcommunity.com", "/linkfilter/", "url"),
+];
+
+/// How many wrappers inside wrappers are unwrapped before giving up.
+const MAX_UNWRAP: usize = 5;
+
+/// Returns the link a user actually means to open: redirect wrappers removed and
+/// query parameters matching `tracking_parameters` stripped.
+pub fn clean_url(url: &Url, tracking_parameters: &[String]) -> Url {
+    let mut url = url.clone();
+    for _ in 0..MAX_UNWRAP {
+        match unwrap_redirect(&url) {
+            Some(target) => url = target,
+            None => break,
+        }
+    }
+    strip_parameters(&mut url, tracking_parameters);
+    url
+}
+
+/// The destination of a known redirect wrapper, if `url` is one.
+fn unwrap_redirect(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+    let (_, _, parameter) = REDIRECTS.iter().find(|(hosts, path, parameter)| {
+        host_matches(hosts, host)
+            && glob_matches(path, url.path())
+            && url.query_pairs().any(|(key, _)| key == *parameter)
+    })?;
+
+    let (_, target) = url.query_pairs().find(|(key, _)| key == parameter)?;
+    Url::parse(&target)
+        .ok()
+        .filter(|target| matches!(target.scheme(), "http" | "https"))
+}
+
+/// Removes denylisted query parameters, keeping the others exactly as they were written.
+fn strip_parameters(url: &mut Url, tracking_parameters: &[String]) {
+    let Some(query) = url.query() else {
+        return;
+    };
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .map(|(key, _)| key.into_owned())
+                .unwrap_or_default();
+            !tracking_parameters.iter().any(|pattern| {
+                glob_matches(&pattern.to_ascii_lowercase(), &key.to_ascii_lowercase())
+            })
+        })
+        .collect();
+
+    if kept.len() == query.split('&').count() {
+        return;
+    }
+    let kept = kept.join("&");
+    url.set_query((!kept.is_empty()).then_some(kept.as_str()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(raw: &str) -> String {
+        let denylist: Vec<String> = DEFAULT_TRACKING_PARAMETERS
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        clean_url(&Url::parse(raw).unwrap(), &denylist).to_string()
+    }
+
+    #[test]
+    fn strips_tracking_parameters() {
+        let cases = [
+            (
+                "https://example.com/a?utm_source=chatgpt.com&utm_medium=referral",
+                "https://example.com/a",
+            ),
+            (
+                "https://example.com/a?id=7&utm_source=chatgpt.com&fbclid=x#top",
+                "https://example.com/a?id=7#top",
+            ),
+            (
+                "https://example.com/?q=a+b&UTM_Campaign=x&page=2",
+                "https://example.com/?q=a+b&page=2",
+            ),
+            (
+                "https://example.com/search?q=%26utm_source",
+                "https://example.com/search?q=%26utm_source",
+            ),
+            ("https://example.com/?utm", "https://example.com/?utm"),
+            ("https://example.com/", "https://example.com/"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(clean(raw), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn unwraps_redirects() {
+        let cases = [
+            (
+                "https://www.google.com/url?sa=t&url=https%3A%2F%2Fexample.com%2Fdoc%3Futm_source%3Dx&usg=abc",
+                "https://example.com/doc",
+            ),
+            (
+                "https://www.google.co.uk/url?q=https://example.org/&sa=D",
+                "https://example.org/",
+            ),
+            (
+                "https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2F%3Ffbclid%3D1&h=AT0",
+                "https://example.com/",
+            ),
+            (
+                "https://www.youtube.com/redirect?event=video_description&q=https%3A%2F%2Fexample.com",
+                "https://example.com/",
+            ),
+            (
+                "https://out.reddit.com/t3_abc?url=https%3A%2F%2Fexample.com%2Fpost&token=x",
+                "https://example.com/post",
+            ),
+            // A wrapper around a wrapper
+            (
+                "https://www.google.com/url?q=https%3A%2F%2Fl.facebook.com%2Fl.php%3Fu%3Dhttps%253A%252F%252Fexample.com%252F",
+                "https://example.com/",
+            ),
+            // Only web links are unwrapped
+            (
+                "https://www.google.com/url?q=javascript:alert(1)",
+                "https://www.google.com/url?q=javascript:alert(1)",
+            ),
+            (
+                "https://www.google.com/search?q=https://example.com",
+                "https://www.google.com/search?q=https://example.com",
+            ),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(clean(raw), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn empty_denylist_keeps_parameters() {
+        let url = Url::parse("https://example.com/?utm_source=x").unwrap();
+        assert_eq!(clean_url(&url, &[]), url);
+    }
+}
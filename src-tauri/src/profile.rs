@@ -0,0 +1,284 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use tauri::{AppHandle, Manager};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Folder under the config and data directories that holds the named profiles.
+const PROFILES_DIR: &str = "profiles";
+
+/// A file with this name next to the executable turns on portable mode.
+const PORTABLE_MARKER: &str = "chatgpt-desktop.portable";
+
+/// Folder next to the executable that holds the data in portable mode.
+const PORTABLE_DIR: &str = "chatgpt-desktop-data";
+
+/// Environment variable naming the folder for all app data.
+const HOME_VAR: &str = "CHATGPT_DESKTOP_HOME";
+
+/// Prefix of the temporary data folders of incognito sessions, followed by the process id.
+const INCOGNITO_PREFIX: &str = "chatgpt-desktop-incognito-";
+
+/// Where the running profile keeps its settings, window state and webview data.
+///
+/// The default profile uses the app directories directly, so installs from before
+/// profiles existed keep their login. Named profiles live in `profiles/<name>`.
+/// An incognito session reads the profile's settings but keeps its webview data
+/// in a temporary folder that is deleted on exit.
+///
+/// In portable mode the app directories are replaced by `config` and `data`
+/// folders inside the portable root.
+pub struct Profile {
+    name: String,
+    config_root: PathBuf,
+    config_dir: PathBuf,
+    data_dir: PathBuf,
+    portable_root: Option<PathBuf>,
+    incognito: bool,
+}
+
+impl Profile {
+    pub fn new<R: tauri::Runtime>(
+        app: &AppHandle<R>,
+        name: Option<&str>,
+        incognito: bool,
+        portable_root: Option<PathBuf>,
+    ) -> Self {
+        let (config_root, data_root) = match &portable_root {
+            Some(root) => (root.join("config"), root.join("data")),
+            None => (
+                app.path()
+                    .app_config_dir()
+                    .expect("Failed to get config dir"),
+                app.path().app_data_dir().expect("Failed to get data dir"),
+            ),
+        };
+
+        let (name, config_dir, data_dir) = match name {
+            Some(name) if name != DEFAULT_PROFILE => (
+                name.to_string(),
+                config_root.join(PROFILES_DIR).join(name),
+                data_root.join(PROFILES_DIR).join(name),
+            ),
+            _ => (DEFAULT_PROFILE.to_string(), config_root.clone(), data_root),
+        };
+        let mut profile = Self {
+            name,
+            config_root,
+            config_dir,
+            data_dir,
+            portable_root,
+            incognito,
+        };
+        if incognito {
+            profile.data_dir = incognito_dir();
+        }
+
+        // The config folder is what makes a profile show up in the tray
+        if let Err(err) = fs::create_dir_all(&profile.config_dir) {
+            eprintln!("Failed to create profile directory: {err}");
+        }
+        profile
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_PROFILE
+    }
+
+    pub fn is_incognito(&self) -> bool {
+        self.incognito
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// The default profile followed by every named profile that has been used, sorted.
+    pub fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.config_root.join(PROFILES_DIR))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| is_valid_name(name) && name != DEFAULT_PROFILE)
+            .collect();
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        names
+    }
+
+    /// Starts the app for profile `name`. If that profile is already running,
+    /// its window comes to the front instead.
+    pub fn launch(&self, name: &str) -> Result<(), String> {
+        self.spawn_app(&["--profile", name])
+    }
+
+    /// Starts an incognito session that uses the settings of this profile.
+    pub fn launch_incognito(&self) -> Result<(), String> {
+        self.spawn_app(&["--profile", &self.name, "--incognito"])
+    }
+
+    /// Runs another copy of the app with the same portable root as this one.
+    fn spawn_app(&self, args: &[&str]) -> Result<(), String> {
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        let mut command = Command::new(exe);
+        command.args(args);
+        if let Some(root) = &self.portable_root {
+            command.arg("--data-dir").arg(root);
+        }
+        let mut child = command.spawn().map_err(|e| e.to_string())?;
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// The folder holding all app data in portable mode, if it is on.
+///
+/// `--data-dir` wins over `CHATGPT_DESKTOP_HOME`, which wins over a
+/// `chatgpt-desktop.portable` file next to the executable.
+pub fn portable_root(data_dir: Option<&Path>) -> Option<PathBuf> {
+    let root = data_dir
+        .map(Path::to_path_buf)
+        .or_else(|| {
+            std::env::var_os(HOME_VAR)
+                .filter(|home| !home.is_empty())
+                .map(PathBuf::from)
+        })
+        .or_else(|| {
+            let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+            exe_dir
+                .join(PORTABLE_MARKER)
+                .exists()
+                .then(|| exe_dir.join(PORTABLE_DIR))
+        })?;
+
+    // Relative paths would change meaning for processes started from elsewhere
+    Some(std::path::absolute(&root).unwrap_or(root))
+}
+
+/// Whether `name` can be used as a profile (and therefore directory) name.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Name used for the single-instance socket, so each profile runs its own
+/// window and a portable copy does not hand its launches to an installed one.
+pub fn instance_name(profile: Option<&str>, portable_root: Option<&Path>) -> String {
+    let mut name = match profile {
+        Some(profile) if profile != DEFAULT_PROFILE => format!("chatgpt-desktop-{profile}"),
+        _ => "chatgpt-desktop".to_string(),
+    };
+    if let Some(root) = portable_root {
+        name.push_str(&format!(
+            "-{:016x}",
+            fnv1a(root.as_os_str().as_encoded_bytes())
+        ));
+    }
+    name
+}
+
+/// 64-bit FNV-1a. Unlike the std hasher its output never changes between Rust
+/// versions, so every build of the app agrees on the socket name.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Temporary webview data folder of this process's incognito session.
+pub fn incognito_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("{INCOGNITO_PREFIX}{}", std::process::id()))
+}
+
+/// Creates the incognito data folder, readable only by the current user.
+///
+/// Fails rather than reusing a folder that already exists, since the temp
+/// directory is shared with other users.
+pub fn create_incognito_dir() -> std::io::Result<()> {
+    let dir = incognito_dir();
+    // Left behind by an earlier process that had the same id
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&dir)
+}
+
+/// Deletes the incognito data folder when the session ends.
+pub fn remove_incognito_dir() {
+    let dir = incognito_dir();
+    if let Err(err) = fs::remove_dir_all(&dir) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            eprintln!("Failed to delete incognito data {}: {err}", dir.display());
+        }
+    }
+}
+
+/// Deletes folders left behind by incognito sessions that did not exit cleanly.
+#[cfg(target_os = "linux")]
+pub fn prune_incognito_dirs() {
+    let Ok(entries) = fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(INCOGNITO_PREFIX))
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if !Path::new("/proc").join(pid.to_string()).exists() {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prune_incognito_dirs() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_names() {
+        assert_eq!(instance_name(None, None), "chatgpt-desktop");
+        assert_eq!(
+            instance_name(Some(DEFAULT_PROFILE), None),
+            "chatgpt-desktop"
+        );
+        assert_eq!(instance_name(Some("work"), None), "chatgpt-desktop-work");
+        assert_eq!(
+            instance_name(Some("work"), Some(Path::new("/media/usb/chatgpt"))),
+            format!("chatgpt-desktop-work-{:016x}", fnv1a(b"/media/usb/chatgpt"))
+        );
+    }
+
+    #[test]
+    fn fnv1a_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -1,10 +1,36 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::fs;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Manager};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::downloads::DownloadRule;
+use crate::external::BrowserOverride;
+use crate::link_cleaner::DEFAULT_TRACKING_PARAMETERS;
+use crate::profile::Profile;
+use crate::url_policy::UrlRule;
+
+/// Layout version written by this build; bump it together with a new entry in `MIGRATIONS`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upgrade steps, where the entry at index `n` turns a version `n` file into version `n + 1`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0_to_v1];
+
+/// How a file older than `SCHEMA_VERSION` was upgraded while loading.
+#[derive(Debug, PartialEq)]
+struct Migration {
+    from: u32,
+    /// Whether any step changed the settings; if not, the file does not need rewriting
+    changed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
+    /// Layout version of the file, used to migrate older files on load
+    pub schema_version: u32,
     /// true = enable notifications, false = disable notifications
     pub notifications_enabled: bool,
     /// true = hide window decorations, false = show window decorations
@@ -15,47 +41,344 @@ pub struct Settings {
     pub close_to_tray: bool,
     /// true = white icon for dark themes, false = dark icon for light themes
     pub tray_icon_light: bool,
+    /// Base ChatGPT address, used for new chats and `--conversation` links
+    pub service_url: String,
+    /// Page opened on launch (a custom GPT, project, temporary chat...); None = service_url
+    pub start_url: Option<String>,
+    /// Where links go, checked in order before the built-in ChatGPT allowlist
+    pub url_rules: Vec<UrlRule>,
+    /// Extra single sign-on hosts (e.g. `.okta.com`) whose popups open in a sign-in window
+    pub identity_providers: Vec<String>,
+    /// Command for opening external links, `%u` is the URL; None = system default browser
+    pub browser: Option<String>,
+    /// Browser commands for specific hosts, checked in order before `browser`
+    pub browser_overrides: Vec<BrowserOverride>,
+    /// true = show the full address and ask before opening a link outside the app
+    pub confirm_external_links: bool,
+    /// Query parameters removed from links before they open in the browser, `*` is a wildcard
+    pub tracking_parameters: Vec<String>,
+    /// The HTTP cache is emptied on startup once it is larger than this; 0 = no limit
+    pub cache_limit_mb: u64,
+    /// Folder downloads are saved to, `~` is the home folder; None = the system Downloads folder
+    pub download_dir: Option<String>,
+    /// Folders for files of some types, checked in order before `download_dir`
+    pub download_rules: Vec<DownloadRule>,
+    /// true = show a save dialog for every download, starting in the folder picked by the rules.
+    /// Linux only; elsewhere downloads are saved as if this were false
+    pub ask_download_location: bool,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             notifications_enabled: true,
             hide_decorations: false,
             show_tray: true,
             close_to_tray: false,
             tray_icon_light: false,
+            service_url: "https://chatgpt.com".to_string(),
+            start_url: None,
+            url_rules: Vec::new(),
+            identity_providers: Vec::new(),
+            browser: None,
+            browser_overrides: Vec::new(),
+            confirm_external_links: false,
+            tracking_parameters: DEFAULT_TRACKING_PARAMETERS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            cache_limit_mb: 512,
+            download_dir: None,
+            download_rules: Vec::new(),
+            ask_download_location: false,
         }
     }
 }
 
 impl Settings {
-    pub fn load<R: tauri::Runtime>(app: &AppHandle<R>) -> Self {
-        let path = Self::get_settings_path(app);
-        if let Ok(contents) = fs::read_to_string(&path) {
-            serde_json::from_str(&contents).unwrap_or_default()
-        } else {
-            Self::default()
+    /// Loads the settings at `path`, reporting why the file could not be read or parsed.
+    ///
+    /// A missing file yields the defaults; an older file is migrated and rewritten.
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let (settings, migration) = Self::parse_and_migrate(&contents)
+                    .map_err(|e| format!("Invalid settings file {}: {e}", path.display()))?;
+                // Rewriting drops the comments, so a file the steps left alone stays as written
+                if let Some(Migration {
+                    from: version,
+                    changed: true,
+                }) = migration
+                {
+                    if let Err(err) = settings.write_migrated(path, &contents, version) {
+                        eprintln!("Failed to migrate settings file {}: {err}", path.display());
+                    }
+                }
+                Ok(settings)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!(
+                "Failed to read settings file {}: {err}",
+                path.display()
+            )),
+        }
+    }
+
+    /// Turns off options that cannot work together with the rest of the settings.
+    pub fn normalize(&mut self) {
+        // Without a tray icon a window closed to the tray could never be brought back
+        if self.close_to_tray && !self.show_tray {
+            eprintln!("close_to_tray requires show_tray, disabling it");
+            self.close_to_tray = false;
+        }
+    }
+
+    /// Parses JSON with comments and trailing commas; missing fields take their defaults.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        Self::parse_and_migrate(contents).map(|(settings, _)| settings)
+    }
+
+    /// Parses a settings file and upgrades it to `SCHEMA_VERSION`, also describing
+    /// the migration when the file was older.
+    fn parse_and_migrate(contents: &str) -> Result<(Self, Option<Migration>), String> {
+        let json = strip_jsonc(contents);
+        let document: Value = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        let Value::Object(mut fields) = document else {
+            return Err("expected a JSON object at the top level".to_string());
+        };
+
+        let version = match fields.get("schema_version") {
+            None => 0,
+            Some(value) => value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or("schema_version must be a non-negative integer")?,
+        };
+
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "schema_version {version} is newer than this version of the app supports ({SCHEMA_VERSION})"
+            ));
         }
+
+        if version == SCHEMA_VERSION {
+            // Deserialize from the text so type errors keep their line and column
+            return serde_json::from_str(&json)
+                .map(|settings| (settings, None))
+                .map_err(|e| e.to_string());
+        }
+
+        fields.remove("schema_version");
+        let original = fields.clone();
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut fields);
+        }
+        let migration = Migration {
+            from: version,
+            changed: fields != original,
+        };
+        fields.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+
+        serde_json::from_value(Value::Object(fields))
+            .map(|settings| (settings, Some(migration)))
+            .map_err(|e| e.to_string())
     }
 
-    pub fn save<R: tauri::Runtime>(&self, app: &AppHandle<R>) -> Result<(), String> {
-        let path = Self::get_settings_path(app);
+    /// Keeps a timestamped copy of the pre-migration file, then writes the upgraded one.
+    fn write_migrated(&self, path: &Path, original: &str, version: u32) -> Result<(), String> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let backup = path.with_file_name(format!("settings.v{version}-{timestamp}.json.bak"));
+
+        fs::write(&backup, original).map_err(|e| e.to_string())?;
+        eprintln!(
+            "Migrated settings from schema v{version} to v{SCHEMA_VERSION}; the original file and its comments are kept at {}",
+            backup.display()
+        );
+        self.write_to(path)
+    }
 
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    /// Writes the settings to `path`, unless the file there is one we failed to parse.
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        // Never clobber a file the user is still fixing by hand
+        if let Ok(existing) = fs::read_to_string(path) {
+            if let Err(err) = Self::parse(&existing) {
+                return Err(format!(
+                    "Not overwriting {} because it could not be parsed: {err}",
+                    path.display()
+                ));
+            }
         }
 
+        self.write_to(path)
+    }
+
+    fn write_to(&self, path: &Path) -> Result<(), String> {
         let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
-        fs::write(&path, contents).map_err(|e| e.to_string())?;
-        Ok(())
+        atomic_write(path, &contents)
+    }
+
+    pub fn get_settings_path<R: tauri::Runtime>(app: &AppHandle<R>) -> PathBuf {
+        app.state::<Profile>().config_dir().join("settings.json")
+    }
+}
+
+/// Writes `contents` to `path` through a temporary file next to it, so a crash
+/// never leaves a truncated file behind.
+pub fn atomic_write(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
+
+/// v0 files predate `schema_version`; their fields carry over unchanged.
+fn migrate_v0_to_v1(_fields: &mut Map<String, Value>) {}
+
+/// Turns JSONC into plain JSON by blanking out comments and trailing commas.
+///
+/// Every removed byte is replaced with a space (newlines are kept), so line and
+/// column numbers in parse errors still point at the original file.
+fn strip_jsonc(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => i = skip_string(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    out[i] = b' ';
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = input[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |pos| i + 2 + pos + 2);
+                for byte in &mut out[i..end] {
+                    if *byte != b'\n' {
+                        *byte = b' ';
+                    }
+                }
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+
+    // Second pass over the comment-free text to drop commas before `}` or `]`
+    let mut i = 0;
+    while i < out.len() {
+        match out[i] {
+            b'"' => i = skip_string(&out, i),
+            b',' => {
+                let next = out[i + 1..].iter().find(|b| !b.is_ascii_whitespace());
+                if matches!(next, Some(b'}') | Some(b']')) {
+                    out[i] = b' ';
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Returns the index just past the string literal starting at `start`.
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0_FULL: &str = include_str!("../tests/fixtures/settings/v0-full.json");
+    const V0_README: &str = include_str!("../tests/fixtures/settings/v0-readme.jsonc");
+    const V0_PARTIAL: &str = include_str!("../tests/fixtures/settings/v0-partial.json");
+    const V1: &str = include_str!("../tests/fixtures/settings/v1.json");
+    const FUTURE: &str = include_str!("../tests/fixtures/settings/future.json");
+
+    #[test]
+    fn every_version_has_a_migration() {
+        assert_eq!(MIGRATIONS.len(), SCHEMA_VERSION as usize);
+    }
+
+    #[test]
+    fn migrates_full_v0_file() {
+        let (settings, migration) = Settings::parse_and_migrate(V0_FULL).unwrap();
+        assert_eq!(
+            migration,
+            Some(Migration {
+                from: 0,
+                changed: false
+            })
+        );
+        assert_eq!(settings.schema_version, SCHEMA_VERSION);
+        assert!(!settings.notifications_enabled);
+        assert!(settings.hide_decorations);
+        assert!(!settings.show_tray);
+        assert!(settings.close_to_tray);
+        assert!(settings.tray_icon_light);
+    }
+
+    #[test]
+    fn migrates_v0_readme_example_with_comments() {
+        let (settings, migration) = Settings::parse_and_migrate(V0_README).unwrap();
+        assert_eq!(migration.map(|m| m.from), Some(0));
+        assert!(settings.notifications_enabled);
+        assert!(settings.show_tray);
+        assert!(!settings.close_to_tray);
+    }
+
+    #[test]
+    fn migrates_partial_v0_file_with_defaults() {
+        let (settings, migration) = Settings::parse_and_migrate(V0_PARTIAL).unwrap();
+        let defaults = Settings::default();
+        assert_eq!(migration.map(|m| m.from), Some(0));
+        assert!(settings.close_to_tray);
+        assert_eq!(
+            settings.notifications_enabled,
+            defaults.notifications_enabled
+        );
+        assert_eq!(settings.show_tray, defaults.show_tray);
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let (settings, migration) = Settings::parse_and_migrate(V1).unwrap();
+        assert_eq!(migration, None);
+        assert!(settings.hide_decorations);
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let err = Settings::parse(FUTURE).unwrap_err();
+        assert!(err.contains("newer"), "{err}");
     }
 
-    fn get_settings_path<R: tauri::Runtime>(app: &AppHandle<R>) -> PathBuf {
-        app.path()
-            .app_config_dir()
-            .expect("Failed to get config dir")
-            .join("settings.json")
+    #[test]
+    fn type_errors_keep_their_position() {
+        let err = Settings::parse("{\n  \"schema_version\": 1,\n  \"show_tray\": \"yes\"\n}")
+            .unwrap_err();
+        assert!(err.contains("line 3"), "{err}");
     }
 }
@@ -0,0 +1,80 @@
+use crate::settings::Settings;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+type Subscriber = Arc<dyn Fn(&Settings, &Settings) + Send + Sync>;
+
+/// The single in-memory copy of the settings, managed as Tauri state.
+///
+/// Every change goes through [`SettingsStore::update`], which persists the new
+/// value before publishing it, so readers never see settings that are not on disk.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl SettingsStore {
+    pub fn new(path: PathBuf, mut settings: Settings) -> Self {
+        settings.normalize();
+        Self {
+            path,
+            settings: Mutex::new(settings),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Registers a callback that receives the old and new settings after each change.
+    pub fn subscribe(&self, subscriber: impl Fn(&Settings, &Settings) + Send + Sync + 'static) {
+        self.subscribers.lock().unwrap().push(Arc::new(subscriber));
+    }
+
+    /// Applies `change`, writes the result to disk and notifies subscribers.
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<Settings, String> {
+        let old = {
+            let mut current = self.settings.lock().unwrap();
+            let mut next = current.clone();
+            change(&mut next);
+            next.normalize();
+            if next == *current {
+                return Ok(next);
+            }
+            next.save_to(&self.path)?;
+            std::mem::replace(&mut *current, next)
+        };
+
+        let new = self.get();
+        self.publish(&old, &new);
+        Ok(new)
+    }
+
+    /// Adopts settings that were changed on disk by someone else, without writing them back.
+    pub fn replace(&self, mut settings: Settings) {
+        settings.normalize();
+        let old = {
+            let mut current = self.settings.lock().unwrap();
+            if *current == settings {
+                return;
+            }
+            std::mem::replace(&mut *current, settings.clone())
+        };
+
+        self.publish(&old, &settings);
+    }
+
+    fn publish(&self, old: &Settings, new: &Settings) {
+        // Clone the list so a subscriber may itself call `update` or `subscribe`
+        let subscribers = self.subscribers.lock().unwrap().clone();
+        for subscriber in subscribers {
+            subscriber(old, new);
+        }
+    }
+}
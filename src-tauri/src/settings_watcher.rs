@@ -0,0 +1,56 @@
+use crate::settings::Settings;
+use crate::settings_store::SettingsStore;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// How long to wait for an editor to finish writing before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Watches the settings file and feeds external edits into the [`SettingsStore`].
+///
+/// The config directory is watched rather than the file itself because most
+/// editors save by replacing the file, which would end a watch on the old inode.
+pub fn watch<R: tauri::Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let path = app.state::<SettingsStore>().path().to_path_buf();
+    let dir = path
+        .parent()
+        .ok_or("Settings file has no parent directory")?
+        .to_path_buf();
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| e.to_string())?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| e.to_string())?;
+
+    let app = app.clone();
+    thread::spawn(move || {
+        // The watcher stops as soon as it is dropped, so it lives on this thread
+        let _watcher = watcher;
+
+        while let Ok(event) = rx.recv() {
+            let Ok(event) = event else { continue };
+            let touches_settings = event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == path.file_name());
+            if !touches_settings || matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+            match Settings::load_from(&path) {
+                Ok(settings) => app.state::<SettingsStore>().replace(settings),
+                Err(err) => crate::report_settings_error(&app, &err),
+            }
+        }
+    });
+
+    Ok(())
+}
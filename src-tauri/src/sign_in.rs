@@ -0,0 +1,71 @@
+use crate::settings_store::SettingsStore;
+use crate::url_policy::UrlAction;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tauri::{
+    webview::NewWindowFeatures, AppHandle, Manager, Theme, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder,
+};
+use url::Url;
+
+/// Numbers sign-in windows so several popups can be open at once.
+static NEXT_WINDOW: AtomicU32 = AtomicU32::new(1);
+
+/// Opens a sign-in popup requested by the main webview in a child window.
+///
+/// The window shares the opener's session. As soon as the provider sends it back
+/// to a ChatGPT page, that page is loaded in the main window and the popup closes.
+pub fn open_window<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    url: &Url,
+    features: NewWindowFeatures,
+) -> tauri::Result<WebviewWindow<R>> {
+    let label = format!("sign-in-{}", NEXT_WINDOW.fetch_add(1, Ordering::Relaxed));
+    let handle = app.clone();
+    let window_label = label.clone();
+
+    let mut builder = WebviewWindowBuilder::new(
+        app,
+        &label,
+        // WebKit loads the popup request into the new view itself
+        WebviewUrl::External("about:blank".parse().expect("about:blank is a valid URL")),
+    )
+    .title(format!("Sign in - {}", url.host_str().unwrap_or_default()))
+    .theme(Some(Theme::Dark))
+    .inner_size(520.0, 680.0)
+    .user_agent(crate::USER_AGENT)
+    .window_features(features)
+    .on_navigation(move |url| handle_navigation(&handle, &window_label, url));
+
+    if let Some(main) = app.get_webview_window("main") {
+        builder = builder.parent(&main)?;
+    }
+
+    builder.build()
+}
+
+/// Lets the provider's pages load and hands the final ChatGPT page to the main window.
+fn handle_navigation<R: tauri::Runtime>(app: &AppHandle<R>, label: &str, url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return true;
+    }
+
+    let policy = crate::url_policy(&app.state::<SettingsStore>().get());
+    match policy.decide(url) {
+        UrlAction::AllowInApp => {
+            if let Some(main) = app.get_webview_window("main") {
+                let _ = main.navigate(url.clone());
+                crate::show_main_window(app);
+            }
+            if let Some(window) = app.get_webview_window(label) {
+                let _ = window.close();
+            }
+            false
+        }
+        UrlAction::Block => {
+            eprintln!("Blocked sign-in navigation to {url}");
+            false
+        }
+        // Providers hop between their own hosts during a login
+        UrlAction::OpenExternally => true,
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+pub enum Instance {
+    /// No other instance is running; this process owns the socket.
+    Primary(UnixListener),
+    /// Another instance is running and has been handed our arguments.
+    Secondary,
+}
+
+/// Directory for the socket: `$XDG_RUNTIME_DIR`, or a per-user folder in the temp dir.
+fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(fallback_dir)
+}
+
+fn fallback_dir() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+    std::env::temp_dir().join(format!("chatgpt-desktop-{user}"))
+}
+
+/// Creates `dir` readable only by the current user, or checks that an existing
+/// one still is.
+///
+/// The temp directory is shared with other users, who could otherwise create
+/// the folder first and answer on our socket.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err),
+    }
+
+    let metadata = fs::symlink_metadata(dir)?;
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a private folder owned by the current user",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Socket of the instance called `name`; each profile runs under its own name.
+pub fn socket_path(name: &str) -> PathBuf {
+    runtime_dir().join(format!("{name}.sock"))
+}
+
+/// Becomes the primary instance `name`, or forwards `args` to the one already running.
+pub fn acquire(name: &str, args: &[String]) -> io::Result<Instance> {
+    let dir = runtime_dir();
+    if std::env::var_os("XDG_RUNTIME_DIR").is_some() {
+        fs::create_dir_all(&dir)?;
+    } else {
+        create_private_dir(&dir)?;
+    }
+
+    // Serialize the connect-or-bind step so two simultaneous launches can't both win
+    let lock = File::create(dir.join(format!("{name}.lock")))?;
+    lock.lock()?;
+
+    let path = socket_path(name);
+    if let Ok(mut stream) = UnixStream::connect(&path) {
+        let mut message = serde_json::to_string(args)?;
+        message.push('\n');
+        stream.write_all(message.as_bytes())?;
+        return Ok(Instance::Secondary);
+    }
+
+    // Nobody answered, so a socket file left here belongs to a crashed instance
+    let _ = fs::remove_file(&path);
+    UnixListener::bind(&path).map(Instance::Primary)
+}
+
+/// Receives the arguments of later launches and passes each set to `on_launch`.
+pub fn listen(listener: UnixListener, on_launch: impl Fn(Vec<String>) + Send + 'static) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+
+            let mut line = String::new();
+            if BufReader::new(stream).read_line(&mut line).is_err() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(args) => on_launch(args),
+                Err(err) => eprintln!("Ignoring malformed message from another instance: {err}"),
+            }
+        }
+    });
+}
+
+/// Removes the socket so the next launch does not have to clean it up.
+pub fn release(name: &str) {
+    let _ = fs::remove_file(socket_path(name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn fallback_folder_is_private() {
+        let root =
+            std::env::temp_dir().join(format!("single-instance-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir(&root).unwrap();
+
+        let dir = root.join("private");
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        // Reusing our own folder is fine
+        create_private_dir(&dir).unwrap();
+
+        let open = root.join("open");
+        fs::create_dir(&open).unwrap();
+        fs::set_permissions(&open, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(create_private_dir(&open).is_err());
+
+        let link = root.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(create_private_dir(&link).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
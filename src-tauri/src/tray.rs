@@ -0,0 +1,426 @@
+use crate::browsing_data::{self, DataKind};
+use crate::profile::Profile;
+use crate::settings::Settings;
+use crate::settings_store::SettingsStore;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use tauri::{
+    menu::{CheckMenuItem, IsMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
+    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
+    AppHandle, Manager,
+};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+pub const TRAY_ID: &str = "main";
+
+/// Size of the browsing data shown in the menu.
+///
+/// Walking the webview folder can take a while once the HTTP cache has grown,
+/// so it is measured on its own thread and the menu is rebuilt when it changes.
+pub struct DataSize {
+    bytes: Arc<AtomicU64>,
+    requests: Sender<()>,
+}
+
+impl DataSize {
+    /// Starts the measuring thread and takes the first measurement.
+    pub fn start<R: tauri::Runtime>(app: &AppHandle<R>) -> Self {
+        let (requests, rx) = mpsc::channel::<()>();
+        let bytes = Arc::new(AtomicU64::new(0));
+        let measured = bytes.clone();
+        let app = app.clone();
+
+        thread::spawn(move || {
+            while rx.recv().is_ok() {
+                // Requests that piled up while measuring are answered by one walk
+                while rx.try_recv().is_ok() {}
+                let size = browsing_data::total_size(&app);
+                if measured.swap(size, Ordering::Relaxed) != size {
+                    set_menu(&app, &app.state::<SettingsStore>().get());
+                }
+            }
+        });
+
+        let _ = requests.send(());
+        Self { bytes, requests }
+    }
+
+    /// The last measured size in bytes.
+    fn get(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Measures again in the background.
+    fn update(&self) {
+        let _ = self.requests.send(());
+    }
+}
+
+/// One entry of the tray menu. The whole menu is described by [`menu_model`].
+enum TrayEntry {
+    Item {
+        id: String,
+        label: String,
+        enabled: bool,
+    },
+    Check {
+        id: String,
+        label: String,
+        checked: bool,
+        enabled: bool,
+    },
+    Separator,
+    Submenu {
+        label: String,
+        entries: Vec<TrayEntry>,
+    },
+}
+
+impl TrayEntry {
+    fn item(id: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::Item {
+            id: id.into(),
+            label: label.into(),
+            enabled: true,
+        }
+    }
+
+    fn check(id: impl Into<String>, label: impl Into<String>, checked: bool) -> Self {
+        Self::Check {
+            id: id.into(),
+            label: label.into(),
+            checked,
+            enabled: true,
+        }
+    }
+
+    fn submenu(label: impl Into<String>, entries: Vec<TrayEntry>) -> Self {
+        Self::Submenu {
+            label: label.into(),
+            entries,
+        }
+    }
+}
+
+/// Describes the tray menu for the given settings and profiles.
+///
+/// Adding an option means adding an entry here and a branch in [`handle_menu_event`].
+fn menu_model(
+    settings: &Settings,
+    profiles: &[String],
+    current_profile: &str,
+    data_size: u64,
+) -> Vec<TrayEntry> {
+    vec![
+        TrayEntry::item("show_hide", "Show/Hide"),
+        TrayEntry::item("downloads", "Downloads"),
+        TrayEntry::item("new_incognito", "New Incognito Window"),
+        TrayEntry::Separator,
+        TrayEntry::check(
+            "toggle_notifications",
+            "Notifications",
+            settings.notifications_enabled,
+        ),
+        TrayEntry::check(
+            "toggle_close_to_tray",
+            "Close to Tray",
+            settings.close_to_tray,
+        ),
+        TrayEntry::submenu(
+            "Appearance",
+            vec![
+                TrayEntry::check(
+                    "toggle_decorations",
+                    "Window Decorations",
+                    !settings.hide_decorations,
+                ),
+                TrayEntry::check(
+                    "toggle_tray_icon",
+                    "Light Tray Icon",
+                    settings.tray_icon_light,
+                ),
+            ],
+        ),
+        TrayEntry::submenu(
+            "Browsing Data",
+            vec![
+                TrayEntry::Item {
+                    id: "data_size".to_string(),
+                    label: format!("Using {}", browsing_data::format_size(data_size)),
+                    enabled: false,
+                },
+                TrayEntry::Separator,
+                TrayEntry::item("clear_cache", "Clear Cache"),
+                TrayEntry::item("clear_cookies", "Clear Cookies and Site Data…"),
+                TrayEntry::item("clear_all_data", "Clear All Data…"),
+            ],
+        ),
+        TrayEntry::submenu(
+            "Profiles",
+            profiles
+                .iter()
+                .map(|name| {
+                    TrayEntry::check(format!("profile:{name}"), name, name == current_profile)
+                })
+                .collect(),
+        ),
+        TrayEntry::Separator,
+        TrayEntry::item("quit", "Quit"),
+    ]
+}
+
+fn tooltip(settings: &Settings, profile: &Profile) -> String {
+    let mut title = if profile.is_default() {
+        "ChatGPT Desktop".to_string()
+    } else {
+        format!("ChatGPT Desktop [{}]", profile.name())
+    };
+    if profile.is_incognito() {
+        title.push_str(" (Incognito)");
+    }
+    let mut tooltip_parts = vec![title.as_str()];
+    if settings.close_to_tray {
+        tooltip_parts.push("(Close to Tray)");
+    }
+    if !settings.notifications_enabled {
+        tooltip_parts.push("(Notifications Off)");
+    }
+    tooltip_parts.join(" ")
+}
+
+fn build_entries<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    entries: &[TrayEntry],
+) -> tauri::Result<Vec<Box<dyn IsMenuItem<R>>>> {
+    let mut items: Vec<Box<dyn IsMenuItem<R>>> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let item: Box<dyn IsMenuItem<R>> = match entry {
+            TrayEntry::Item { id, label, enabled } => Box::new(MenuItem::with_id(
+                app,
+                id.as_str(),
+                label,
+                *enabled,
+                None::<&str>,
+            )?),
+            TrayEntry::Check {
+                id,
+                label,
+                checked,
+                enabled,
+            } => Box::new(CheckMenuItem::with_id(
+                app,
+                id.as_str(),
+                label,
+                *enabled,
+                *checked,
+                None::<&str>,
+            )?),
+            TrayEntry::Separator => Box::new(PredefinedMenuItem::separator(app)?),
+            TrayEntry::Submenu { label, entries } => {
+                let children = build_entries(app, entries)?;
+                let refs: Vec<&dyn IsMenuItem<R>> = children.iter().map(|c| c.as_ref()).collect();
+                Box::new(Submenu::with_items(app, label, true, &refs)?)
+            }
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn build_menu<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    settings: &Settings,
+) -> tauri::Result<Menu<R>> {
+    let profile = app.state::<Profile>();
+    let model = menu_model(
+        settings,
+        &profile.list(),
+        profile.name(),
+        app.state::<DataSize>().get(),
+    );
+    let items = build_entries(app, &model)?;
+    let refs: Vec<&dyn IsMenuItem<R>> = items.iter().map(|i| i.as_ref()).collect();
+    Menu::with_items(app, &refs)
+}
+
+/// Rebuilds the icon, tooltip and menu of the tray, if there is one, from `settings`.
+pub fn refresh<R: tauri::Runtime>(app: &AppHandle<R>, settings: &Settings) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+
+    let _ = tray.set_icon(Some(load_tray_icon(app, settings.tray_icon_light)));
+    let _ = tray.set_tooltip(Some(tooltip(settings, &app.state::<Profile>())));
+    set_menu(app, settings);
+    app.state::<DataSize>().update();
+}
+
+/// Rebuilds the tray menu with the last measured data size.
+fn set_menu<R: tauri::Runtime>(app: &AppHandle<R>, settings: &Settings) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match build_menu(app, settings) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(err) => eprintln!("Failed to build tray menu: {err}"),
+    }
+}
+
+pub fn create<R: tauri::Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
+    let settings = app.state::<SettingsStore>().get();
+    let menu = build_menu(app, &settings)?;
+
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(load_tray_icon(app, settings.tray_icon_light))
+        .tooltip(tooltip(&settings, &app.state::<Profile>()))
+        .menu(&menu)
+        .on_menu_event(|app, event| handle_menu_event(app, event.id.as_ref()))
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                toggle_main_window(tray.app_handle());
+            }
+        })
+        .build(app)?;
+
+    app.state::<DataSize>().update();
+    Ok(())
+}
+
+pub fn remove<R: tauri::Runtime>(app: &AppHandle<R>) {
+    app.remove_tray_by_id(TRAY_ID);
+}
+
+fn handle_menu_event<R: tauri::Runtime>(app: &AppHandle<R>, id: &str) {
+    let result = match id {
+        "show_hide" => {
+            toggle_main_window(app);
+            Ok(())
+        }
+        "downloads" => crate::show_downloads_window(app).map_err(|e| e.to_string()),
+        "clear_cache" => crate::clear_browsing_data(app, DataKind::Cache),
+        "clear_cookies" => {
+            confirm_clear(app, DataKind::Session);
+            Ok(())
+        }
+        "clear_all_data" => {
+            confirm_clear(app, DataKind::All);
+            Ok(())
+        }
+        "new_incognito" => app.state::<Profile>().launch_incognito(),
+        "toggle_notifications" => crate::toggle_notifications(app.state()).map(drop),
+        "toggle_decorations" => crate::toggle_decorations(app.state()).map(drop),
+        "toggle_close_to_tray" => crate::toggle_close_to_tray(app.state()).map(drop),
+        "toggle_tray_icon" => crate::toggle_tray_icon(app.state()).map(drop),
+        "quit" => {
+            app.exit(0);
+            Ok(())
+        }
+        _ => {
+            if let Some(name) = id.strip_prefix("profile:") {
+                open_profile(app, name);
+            }
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("Tray action {id} failed: {err}");
+        // Check items flip themselves when clicked, so put them back in sync
+        refresh(app, &app.state::<SettingsStore>().get());
+    }
+}
+
+/// Asks before clearing data that signs the user out.
+fn confirm_clear<R: tauri::Runtime>(app: &AppHandle<R>, kind: DataKind) {
+    let handle = app.clone();
+    app.dialog()
+        .message(format!(
+            "Clear {} for the {} profile? You will be signed out of ChatGPT.",
+            kind.describe(),
+            app.state::<Profile>().name()
+        ))
+        .title("Clear Browsing Data")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Clear".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |confirmed| {
+            if confirmed {
+                if let Err(err) = crate::clear_browsing_data(&handle, kind) {
+                    eprintln!("Failed to clear {}: {err}", kind.describe());
+                }
+            }
+        });
+}
+
+/// Brings up the window of profile `name`, starting it if it is not running.
+fn open_profile<R: tauri::Runtime>(app: &AppHandle<R>, name: &str) {
+    let profile = app.state::<Profile>();
+    if name == profile.name() {
+        crate::show_main_window(app);
+    } else if let Err(err) = profile.launch(name) {
+        eprintln!("Failed to open profile {name}: {err}");
+    }
+    // The click toggled the entry, but only the running profile stays checked
+    refresh(app, &app.state::<SettingsStore>().get());
+}
+
+fn toggle_main_window<R: tauri::Runtime>(app: &AppHandle<R>) {
+    if let Some(window) = app.get_webview_window("main") {
+        if window.is_visible().unwrap_or(false) {
+            let _ = window.hide();
+        } else {
+            crate::show_main_window(app);
+        }
+    }
+}
+
+fn load_tray_icon<R: tauri::Runtime>(
+    app: &AppHandle<R>,
+    use_light: bool,
+) -> tauri::image::Image<'static> {
+    if use_light {
+        let icon_name = "icon-light-32x32.png";
+
+        let search_paths = vec![
+            app.path()
+                .resource_dir()
+                .ok()
+                .map(|d| d.join("icons").join(icon_name)),
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|p| p.join("icons").join(icon_name))),
+            std::env::current_dir()
+                .ok()
+                .map(|d| d.join("src-tauri").join("icons").join(icon_name)),
+        ];
+
+        for icon_path in search_paths.into_iter().flatten() {
+            if let Ok(img_data) = std::fs::read(&icon_path) {
+                if let Ok(img) = image::load_from_memory(&img_data) {
+                    let rgba = img.to_rgba8();
+                    let (width, height) = rgba.dimensions();
+                    eprintln!("✓ Loaded tray icon from: {}", icon_path.display());
+                    return tauri::image::Image::new_owned(rgba.into_raw(), width, height);
+                }
+            }
+        }
+    }
+
+    if let Some(default_icon) = app.default_window_icon() {
+        let rgba = default_icon.rgba().to_vec();
+        tauri::image::Image::new_owned(rgba, default_icon.width(), default_icon.height())
+    } else {
+        tauri::image::Image::new_owned(vec![0, 0, 0, 0], 1, 1)
+    }
+}
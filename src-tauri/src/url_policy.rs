@@ -0,0 +1,464 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Hosts whose pages stay in the app window. Subdomains are included.
+const IN_APP_DOMAINS: &[&str] = &[
+    "chatgpt.com",
+    "openai.com",
+    "oaistatic.com",
+    "oaiusercontent.com",
+];
+
+/// Non-HTTP schemes the webview handles itself (blank pages, inline data, websockets).
+const IN_APP_SCHEMES: &[&str] = &["about", "data", "blob", "ws", "wss"];
+
+/// Single sign-on providers offered on the ChatGPT login page. Their popups open
+/// in an in-app sign-in window so the session ends up in the app's cookie store.
+const IDENTITY_PROVIDERS: &[&str] = &[
+    "accounts.google.com",
+    "login.microsoftonline.com",
+    "login.live.com",
+    "appleid.apple.com",
+    "*.auth0.com",
+];
+
+/// What to do with a URL the webview wants to open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UrlAction {
+    AllowInApp,
+    OpenExternally,
+    Block,
+}
+
+/// A user rule from the `url_rules` setting. The first matching rule wins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlRule {
+    pub action: UrlAction,
+    /// `docs.example.com` (exact), `*.example.com` (glob) or `.example.com`
+    /// (the domain and all its subdomains)
+    pub host: String,
+    /// Only match paths starting with this, e.g. `/docs/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+}
+
+impl UrlRule {
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        host_matches(&self.host, host)
+            && self
+                .path_prefix
+                .as_deref()
+                .is_none_or(|prefix| url.path().starts_with(prefix))
+    }
+}
+
+/// Matches a host against an exact name, a `*` glob or a `.suffix` pattern.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    if let Some(domain) = pattern.strip_prefix('.') {
+        host == domain || host.ends_with(&pattern)
+    } else if pattern.contains('*') {
+        glob_matches(&pattern, host)
+    } else {
+        host == pattern
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of characters.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Decides which URLs stay inside the app, go to the system browser or are blocked.
+///
+/// This is the only definition of the policy: the Rust handlers call
+/// [`UrlPolicy::decide`] and the injected click handler is generated from the
+/// same data by [`UrlPolicy::script`]. User rules are checked in order before
+/// the built-in allowlist.
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlPolicy {
+    rules: Vec<UrlRule>,
+    domains: Vec<String>,
+    schemes: Vec<String>,
+    /// Only consulted on the Rust side, the page never opens sign-in windows itself
+    #[serde(skip)]
+    identity_providers: Vec<String>,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self::new(Vec::new(), Vec::new())
+    }
+}
+
+impl UrlPolicy {
+    /// Builds the policy from the user's rules and extra identity provider host
+    /// patterns (for example a company Okta domain).
+    pub fn new(rules: Vec<UrlRule>, identity_providers: Vec<String>) -> Self {
+        Self {
+            rules,
+            domains: IN_APP_DOMAINS.iter().map(|d| d.to_string()).collect(),
+            schemes: IN_APP_SCHEMES.iter().map(|s| s.to_string()).collect(),
+            identity_providers: IDENTITY_PROVIDERS
+                .iter()
+                .map(|p| p.to_string())
+                .chain(identity_providers)
+                .collect(),
+        }
+    }
+
+    pub fn decide(&self, url: &Url) -> UrlAction {
+        if let Some(rule) = self.rule_for(url) {
+            return rule.action;
+        }
+
+        if self.allows_builtin(url) {
+            UrlAction::AllowInApp
+        } else {
+            UrlAction::OpenExternally
+        }
+    }
+
+    pub fn allows(&self, url: &Url) -> bool {
+        self.decide(url) == UrlAction::AllowInApp
+    }
+
+    /// Whether `url` may be opened as the main page from the command line or
+    /// the settings. Only http(s) addresses on an in-app host qualify; the other
+    /// in-app schemes are for what a loaded page fetches or navigates to.
+    pub fn allows_page(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https") && self.allows(url)
+    }
+
+    /// Whether `url` starts a single sign-on flow that belongs in a sign-in window.
+    /// A user rule for the same URL takes precedence.
+    pub fn is_sign_in(&self, url: &Url) -> bool {
+        url.scheme() == "https"
+            && self.rule_for(url).is_none()
+            && url.host_str().is_some_and(|host| {
+                self.identity_providers
+                    .iter()
+                    .any(|pattern| host_matches(pattern, host))
+            })
+    }
+
+    fn rule_for(&self, url: &Url) -> Option<&UrlRule> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        self.rules.iter().find(|rule| rule.matches(url))
+    }
+
+    fn allows_builtin(&self, url: &Url) -> bool {
+        match url.scheme() {
+            "http" | "https" => url.host_str().is_some_and(|host| self.allows_host(host)),
+            scheme => self.schemes.iter().any(|s| s == scheme),
+        }
+    }
+
+    fn allows_host(&self, host: &str) -> bool {
+        self.domains.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+
+    /// JavaScript defining `urlDecision(url)` with the same rules as [`UrlPolicy::decide`].
+    ///
+    /// The policy can be swapped at runtime with the script from [`UrlPolicy::update_script`].
+    pub fn script(&self) -> String {
+        format!(
+            r#"
+    let urlPolicy = {policy};
+    window.addEventListener('chatgpt-desktop:url-policy', function(e) {{
+        urlPolicy = e.detail;
+    }});
+    function hostMatches(pattern, host) {{
+        pattern = pattern.toLowerCase();
+        if (pattern.startsWith('.')) {{
+            return host === pattern.slice(1) || host.endsWith(pattern);
+        }}
+        if (pattern.includes('*')) {{
+            const escaped = pattern.split('*').map(part =>
+                part.replace(/[.+?^${{}}()|[\]\\]/g, '\\$&')
+            );
+            return new RegExp('^' + escaped.join('.*') + '$').test(host);
+        }}
+        return host === pattern;
+    }}
+    function urlDecision(url) {{
+        const scheme = url.protocol.replace(/:$/, '');
+        const isHttp = scheme === 'http' || scheme === 'https';
+        if (isHttp) {{
+            const rule = urlPolicy.rules.find(rule =>
+                url.hostname !== '' && hostMatches(rule.host, url.hostname) &&
+                (!rule.path_prefix || url.pathname.startsWith(rule.path_prefix))
+            );
+            if (rule) return rule.action;
+        }}
+        const allowed = isHttp
+            ? url.hostname !== '' && urlPolicy.domains.some(domain =>
+                url.hostname === domain || url.hostname.endsWith('.' + domain))
+            : urlPolicy.schemes.includes(scheme);
+        return allowed ? 'allow-in-app' : 'open-externally';
+    }}
+"#,
+            policy = self.to_json()
+        )
+    }
+
+    /// JavaScript that hands this policy to an already loaded page.
+    pub fn update_script(&self) -> String {
+        format!(
+            "window.dispatchEvent(new CustomEvent('chatgpt-desktop:url-policy', {{ detail: {} }}));",
+            self.to_json()
+        )
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("the url policy always serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASES: &[(&str, bool)] = &[
+        ("https://chatgpt.com", true),
+        ("https://chatgpt.com/c/1234", true),
+        ("https://chat.openai.com/", true),
+        ("https://openai.com/policies", true),
+        ("https://auth.openai.com/log-in", true),
+        ("https://cdn.oaistatic.com/assets/app.js", true),
+        ("https://files.oaiusercontent.com/file-1", true),
+        ("http://chatgpt.com", true),
+        ("wss://ws.chatgpt.com/socket", true),
+        ("about:blank", true),
+        ("blob:https://chatgpt.com/0f0e", true),
+        ("data:text/plain,hi", true),
+        ("https://example.com", false),
+        ("https://notopenai.com", false),
+        ("https://openai.com.evil.example", false),
+        ("https://evilchatgpt.com", false),
+        ("mailto:someone@example.com", false),
+        ("file:///etc/passwd", false),
+        ("javascript:alert(1)", false),
+    ];
+
+    const RULE_CASES: &[(&str, UrlAction)] = &[
+        ("https://docs.example.com/guide", UrlAction::AllowInApp),
+        ("https://docs.example.com/other", UrlAction::OpenExternally),
+        (
+            "https://help.openai.com/en/articles/1",
+            UrlAction::OpenExternally,
+        ),
+        ("https://intranet.corp.example/wiki", UrlAction::AllowInApp),
+        ("https://corp.example/", UrlAction::AllowInApp),
+        ("https://tracker.ads.example.net/x", UrlAction::Block),
+        ("https://ads.example.net/x", UrlAction::OpenExternally),
+        ("https://chatgpt.com/", UrlAction::AllowInApp),
+        ("https://example.org/", UrlAction::OpenExternally),
+    ];
+
+    fn rules_policy() -> UrlPolicy {
+        let rule = |action, host: &str, path_prefix: Option<&str>| UrlRule {
+            action,
+            host: host.to_string(),
+            path_prefix: path_prefix.map(str::to_string),
+        };
+        UrlPolicy::new(
+            vec![
+                rule(UrlAction::AllowInApp, "docs.example.com", Some("/guide")),
+                rule(UrlAction::OpenExternally, "help.openai.com", None),
+                rule(UrlAction::AllowInApp, ".corp.example", None),
+                rule(UrlAction::Block, "*.ads.example.net", None),
+                rule(UrlAction::Block, "login.live.com", None),
+            ],
+            vec![".okta.com".to_string()],
+        )
+    }
+
+    #[test]
+    fn allowlist_decisions() {
+        let policy = UrlPolicy::default();
+        for (raw, expected) in CASES {
+            let url = Url::parse(raw).unwrap();
+            assert_eq!(policy.allows(&url), *expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn pages_must_be_http_on_an_in_app_host() {
+        let policy = UrlPolicy::default();
+        let cases = [
+            ("https://chatgpt.com/g/g-1", true),
+            ("http://chat.openai.com/", true),
+            ("https://example.com/", false),
+            ("data:text/html,<h1>hi</h1>", false),
+            ("about:blank", false),
+            ("blob:https://chatgpt.com/0f0e", false),
+            ("wss://ws.chatgpt.com/socket", false),
+        ];
+        for (raw, expected) in cases {
+            let url = Url::parse(raw).unwrap();
+            assert_eq!(policy.allows_page(&url), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn rules_are_checked_in_order_before_the_allowlist() {
+        let policy = rules_policy();
+        for (raw, expected) in RULE_CASES {
+            let url = Url::parse(raw).unwrap();
+            assert_eq!(policy.decide(&url), *expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn sign_in_hosts() {
+        let policy = rules_policy();
+        let cases = [
+            ("https://accounts.google.com/o/oauth2/auth", true),
+            ("https://login.microsoftonline.com/common/oauth2", true),
+            ("https://appleid.apple.com/auth/authorize", true),
+            ("https://openai.auth0.com/authorize", true),
+            ("https://acme.okta.com/oauth2/v1/authorize", true),
+            ("http://accounts.google.com/", false),
+            ("https://login.live.com/oauth20", false),
+            ("https://mail.google.com/", false),
+            ("https://auth.openai.com/log-in", false),
+        ];
+        for (raw, expected) in cases {
+            let url = Url::parse(raw).unwrap();
+            assert_eq!(policy.is_sign_in(&url), expected, "{raw}");
+        }
+        assert!(!UrlPolicy::default().is_sign_in(&Url::parse("https://acme.okta.com/").unwrap()));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches("*.example.com", "a.b.example.com"));
+        assert!(glob_matches("docs-*.example.com", "docs-eu.example.com"));
+        assert!(!glob_matches("*.example.com", "example.com"));
+        assert!(!glob_matches("a*a", "a"));
+        assert!(glob_matches("*", "anything"));
+    }
+
+    #[test]
+    fn script_embeds_the_same_policy() {
+        let policy = UrlPolicy::default();
+        let script = policy.script();
+        let literal = script
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("let urlPolicy = "))
+            .and_then(|rest| rest.strip_suffix(';'))
+            .expect("script declares urlPolicy");
+        let embedded: serde_json::Value = serde_json::from_str(literal).unwrap();
+
+        assert_eq!(embedded["domains"], serde_json::json!(IN_APP_DOMAINS));
+        assert_eq!(embedded["schemes"], serde_json::json!(IN_APP_SCHEMES));
+    }
+
+    /// Fields the script reads after `prefix`, e.g. `urlPolicy.` or `rule.`.
+    fn fields_read<'a>(script: &'a str, prefix: &str) -> Vec<&'a str> {
+        script
+            .match_indices(prefix)
+            .map(|(index, _)| {
+                let rest = &script[index + prefix.len()..];
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                &rest[..end]
+            })
+            .collect()
+    }
+
+    /// Runs without node: the names the script relies on are the ones Rust produces.
+    #[test]
+    fn script_reads_what_rust_serializes() {
+        let policy = rules_policy();
+        let script = policy.script();
+        let embedded: serde_json::Value = serde_json::from_str(&policy.to_json()).unwrap();
+
+        for field in fields_read(&script, "urlPolicy.") {
+            assert!(embedded.get(field).is_some(), "urlPolicy.{field}");
+        }
+        let rule = &embedded["rules"][0];
+        for field in fields_read(&script, "rule.") {
+            assert!(rule.get(field).is_some(), "rule.{field}");
+        }
+
+        for action in [UrlAction::AllowInApp, UrlAction::OpenExternally] {
+            let name = serde_json::to_value(action).unwrap();
+            let literal = format!("'{}'", name.as_str().unwrap());
+            assert!(script.contains(&literal), "{literal}");
+        }
+        assert!(script.contains("function hostMatches(pattern, host)"));
+        assert!(script.contains("function urlDecision(url)"));
+    }
+
+    /// Evaluates `urls` with the script generated for `policy`. Needs `node` on the PATH.
+    fn script_decisions(policy: &UrlPolicy, urls: &[&str]) -> Vec<UrlAction> {
+        use std::process::Command;
+
+        let program = format!(
+            "const window = new EventTarget();\n{}\nconsole.log(JSON.stringify({}.map(u => urlDecision(new URL(u)))));",
+            policy.script(),
+            serde_json::to_string(urls).unwrap()
+        );
+        let output = Command::new("node")
+            .arg("-e")
+            .arg(program)
+            .output()
+            .expect("failed to run node; these tests need it on the PATH");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs node; run with `cargo test -- --ignored`"]
+    fn script_decisions_match_rust() {
+        let urls: Vec<&str> = CASES.iter().map(|(raw, _)| *raw).collect();
+        let decisions = script_decisions(&UrlPolicy::default(), &urls);
+        for ((raw, expected), decision) in CASES.iter().zip(decisions) {
+            assert_eq!(decision == UrlAction::AllowInApp, *expected, "{raw}");
+        }
+    }
+
+    #[test]
+    #[ignore = "needs node; run with `cargo test -- --ignored`"]
+    fn script_rule_decisions_match_rust() {
+        let urls: Vec<&str> = RULE_CASES.iter().map(|(raw, _)| *raw).collect();
+        let decisions = script_decisions(&rules_policy(), &urls);
+        for ((raw, expected), decision) in RULE_CASES.iter().zip(decisions) {
+            assert_eq!(decision, *expected, "{raw}");
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::profile::Profile;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WebviewWindow};
+
+/// How long the window has to stay still before its geometry is written.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Geometry of the main window in physical pixels, as stored in `window-state.json`.
+///
+/// Position and size are those of the outer frame, decorations included, so the
+/// whole window is what gets fitted onto a monitor. They always describe the
+/// restored (non-maximized) window, so un-maximizing after a restart returns to
+/// where the user left it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    /// Name of the monitor the window was on, preferred when it is still connected
+    pub monitor: Option<String>,
+}
+
+/// A monitor's usable area in physical pixels.
+#[derive(Debug, Clone)]
+pub struct Area {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Area {
+    /// Number of pixels shared with the given window rectangle.
+    fn overlap(&self, state: &WindowState) -> i64 {
+        let left = self.x.max(state.x) as i64;
+        let top = self.y.max(state.y) as i64;
+        let right = (self.x as i64 + self.width as i64).min(state.x as i64 + state.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(state.y as i64 + state.height as i64);
+        (right - left).max(0) * (bottom - top).max(0)
+    }
+}
+
+impl WindowState {
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents)
+            .map_err(|err| eprintln!("Ignoring invalid {}: {err}", path.display()))
+            .ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    /// Moves and shrinks the window so it lies entirely on one of `monitors`.
+    ///
+    /// The monitor it was saved on wins if it is still connected, then the one it
+    /// overlaps most, and finally the first (primary) monitor.
+    pub fn clamp_to(mut self, monitors: &[Area]) -> Self {
+        let target = monitors
+            .iter()
+            .find(|m| m.name.is_some() && m.name == self.monitor)
+            .or_else(|| {
+                monitors
+                    .iter()
+                    .filter(|m| m.overlap(&self) > 0)
+                    .max_by_key(|m| m.overlap(&self))
+            })
+            .or_else(|| monitors.first());
+
+        let Some(area) = target else {
+            return self;
+        };
+
+        self.width = self.width.min(area.width);
+        self.height = self.height.min(area.height);
+        let max_x = area.x + (area.width - self.width) as i32;
+        let max_y = area.y + (area.height - self.height) as i32;
+        self.x = self.x.clamp(area.x, max_x);
+        self.y = self.y.clamp(area.y, max_y);
+        self.monitor = area.name.clone();
+        self
+    }
+
+    /// Reads the current geometry of `window`, keeping the last restored
+    /// position and size from `previous` while it is maximized or fullscreen.
+    fn capture<R: tauri::Runtime>(
+        window: &WebviewWindow<R>,
+        previous: Option<&WindowState>,
+    ) -> Option<Self> {
+        if window.is_minimized().unwrap_or(false) {
+            return previous.cloned();
+        }
+
+        let maximized = window.is_maximized().unwrap_or(false);
+        let fullscreen = window.is_fullscreen().unwrap_or(false);
+        let monitor = window
+            .current_monitor()
+            .ok()
+            .flatten()
+            .and_then(|m| m.name().cloned());
+
+        match previous {
+            Some(previous) if maximized || fullscreen => Some(Self {
+                maximized,
+                fullscreen,
+                monitor,
+                ..previous.clone()
+            }),
+            _ => {
+                let position = window.outer_position().ok()?;
+                let size = window.outer_size().ok()?;
+                Some(Self {
+                    x: position.x,
+                    y: position.y,
+                    width: size.width,
+                    height: size.height,
+                    maximized,
+                    fullscreen,
+                    monitor,
+                })
+            }
+        }
+    }
+}
+
+pub fn get_window_state_path<R: tauri::Runtime>(app: &AppHandle<R>) -> PathBuf {
+    app.state::<Profile>()
+        .config_dir()
+        .join("window-state.json")
+}
+
+/// Applies the saved geometry to a freshly built, still hidden window.
+pub fn restore<R: tauri::Runtime>(window: &WebviewWindow<R>, path: &Path) {
+    let Some(state) = WindowState::load(path) else {
+        return;
+    };
+
+    let monitors: Vec<Area> = window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|m| Area {
+            name: m.name().cloned(),
+            x: m.work_area().position.x,
+            y: m.work_area().position.y,
+            width: m.work_area().size.width,
+            height: m.work_area().size.height,
+        })
+        .collect();
+    let state = state.clamp_to(&monitors);
+
+    // `set_size` takes the inner size, so take off whatever the frame adds
+    let (frame_width, frame_height) = match (window.outer_size(), window.inner_size()) {
+        (Ok(outer), Ok(inner)) => (
+            outer.width.saturating_sub(inner.width),
+            outer.height.saturating_sub(inner.height),
+        ),
+        _ => (0, 0),
+    };
+    let _ = window.set_size(PhysicalSize::new(
+        state.width.saturating_sub(frame_width).max(1),
+        state.height.saturating_sub(frame_height).max(1),
+    ));
+    let _ = window.set_position(PhysicalPosition::new(state.x, state.y));
+    if state.maximized {
+        let _ = window.maximize();
+    }
+    if state.fullscreen {
+        let _ = window.set_fullscreen(true);
+    }
+}
+
+/// Saves the window geometry whenever it settles after a move or resize.
+///
+/// Returns a sender that schedules a save; the caller should also call
+/// [`save_now`] when the window is about to close.
+pub fn track<R: tauri::Runtime>(window: &WebviewWindow<R>, path: PathBuf) -> Sender<()> {
+    let (tx, rx) = mpsc::channel::<()>();
+    let window = window.clone();
+
+    thread::spawn(move || {
+        while rx.recv().is_ok() {
+            while rx.recv_timeout(SAVE_DELAY).is_ok() {}
+            save_now(&window, &path);
+        }
+    });
+
+    tx
+}
+
+pub fn save_now<R: tauri::Runtime>(window: &WebviewWindow<R>, path: &Path) {
+    let previous = WindowState::load(path);
+    if let Some(state) = WindowState::capture(window, previous.as_ref()) {
+        if previous.as_ref() != Some(&state) {
+            if let Err(err) = state.save(path) {
+                eprintln!("Failed to save window state: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(name: &str, x: i32, y: i32, width: u32, height: u32) -> Area {
+        Area {
+            name: Some(name.to_string()),
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn state(x: i32, y: i32, width: u32, height: u32, monitor: Option<&str>) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width,
+            height,
+            maximized: false,
+            fullscreen: false,
+            monitor: monitor.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn overlap_counts_shared_pixels() {
+        let monitor = area("A", 0, 0, 1920, 1080);
+        assert_eq!(monitor.overlap(&state(100, 100, 800, 600, None)), 800 * 600);
+        assert_eq!(
+            monitor.overlap(&state(1820, 980, 800, 600, None)),
+            100 * 100
+        );
+        assert_eq!(
+            monitor.overlap(&state(-700, -500, 800, 600, None)),
+            100 * 100
+        );
+        assert_eq!(monitor.overlap(&state(1920, 0, 800, 600, None)), 0);
+        assert_eq!(monitor.overlap(&state(-5000, -5000, 800, 600, None)), 0);
+    }
+
+    #[test]
+    fn disconnected_monitor_falls_back_to_the_one_overlapped_most() {
+        let monitors = [
+            area("left", 0, 0, 1920, 1080),
+            area("right", 1920, 0, 1920, 1080),
+        ];
+        let clamped = state(1800, 100, 800, 600, Some("gone")).clamp_to(&monitors);
+        assert_eq!(clamped, state(1920, 100, 800, 600, Some("right")));
+
+        // Nothing overlaps: the primary monitor takes it
+        let clamped = state(9000, 9000, 800, 600, Some("gone")).clamp_to(&monitors);
+        assert_eq!(clamped, state(1120, 480, 800, 600, Some("left")));
+    }
+
+    #[test]
+    fn saved_monitor_wins_when_still_connected() {
+        let monitors = [
+            area("left", 0, 0, 1920, 1080),
+            area("right", 1920, 0, 1920, 1080),
+        ];
+        let clamped = state(100, 100, 800, 600, Some("right")).clamp_to(&monitors);
+        assert_eq!(clamped, state(1920, 100, 800, 600, Some("right")));
+    }
+
+    #[test]
+    fn window_larger_than_the_monitor_is_shrunk() {
+        let monitors = [area("small", 0, 0, 1280, 720)];
+        let clamped = state(200, 200, 2560, 1440, Some("small")).clamp_to(&monitors);
+        assert_eq!(clamped, state(0, 0, 1280, 720, Some("small")));
+    }
+
+    #[test]
+    fn negative_coordinates_are_kept_on_monitors_left_of_primary() {
+        let monitors = [
+            area("primary", 0, 0, 1920, 1080),
+            area("left", -1280, -200, 1280, 1024),
+        ];
+        let clamped = state(-1000, -100, 800, 600, None).clamp_to(&monitors);
+        assert_eq!(clamped, state(-1000, -100, 800, 600, Some("left")));
+
+        let clamped = state(-1500, -300, 800, 600, None).clamp_to(&monitors);
+        assert_eq!(clamped, state(-1280, -200, 800, 600, Some("left")));
+    }
+
+    #[test]
+    fn no_monitors_leaves_the_state_alone() {
+        let saved = state(-1500, -300, 800, 600, Some("gone"));
+        assert_eq!(saved.clone().clamp_to(&[]), saved);
+    }
+}
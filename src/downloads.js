@@ -0,0 +1,113 @@
+// Download manager: lists the download history kept by the app and acts on its entries.
+const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
+
+const list = document.getElementById("downloads");
+const search = document.getElementById("search");
+const empty = document.getElementById("empty");
+const error = document.getElementById("error");
+
+const STATUS_LABELS = {
+  in_progress: "Downloading…",
+  completed: "Completed",
+  failed: "Failed",
+};
+
+function formatSize(bytes) {
+  if (bytes == null) return "";
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return unit === 0 ? `${value} B` : `${value.toFixed(1)} ${units[unit]}`;
+}
+
+function showError(message) {
+  error.textContent = String(message);
+  error.hidden = false;
+}
+
+async function run(command, args) {
+  error.hidden = true;
+  try {
+    await invoke(command, args);
+  } catch (err) {
+    showError(err);
+  }
+}
+
+function button(label, onClick) {
+  const element = document.createElement("button");
+  element.textContent = label;
+  element.addEventListener("click", onClick);
+  return element;
+}
+
+function render(download) {
+  const item = document.createElement("li");
+  item.className = download.status;
+
+  const name = document.createElement("strong");
+  name.textContent = download.filename;
+  name.title = download.destination;
+
+  const details = document.createElement("small");
+  details.textContent = [
+    STATUS_LABELS[download.status],
+    formatSize(download.size),
+    new Date(download.started_at * 1000).toLocaleString(),
+  ]
+    .filter(Boolean)
+    .join(" · ");
+
+  const source = document.createElement("small");
+  source.className = "source";
+  source.textContent = download.url;
+  source.title = download.url;
+
+  const actions = document.createElement("div");
+  actions.className = "actions";
+  const { id } = download;
+  if (download.status === "completed") {
+    actions.append(
+      button("Open", () => run("open_download", { id })),
+      button("Show in Folder", () => run("show_download_in_folder", { id })),
+      button("Delete", () => {
+        if (confirm(`Delete ${download.filename} from disk?`)) {
+          run("delete_download", { id, deleteFile: true });
+        }
+      }),
+    );
+  }
+  // Blob addresses die with the page that created them
+  if (download.status === "failed" && !download.url.startsWith("blob:")) {
+    actions.append(button("Retry", () => run("retry_download", { id })));
+  }
+  if (download.status !== "in_progress") {
+    actions.append(button("Remove", () => run("delete_download", { id, deleteFile: false })));
+  }
+
+  const text = document.createElement("div");
+  text.className = "text";
+  text.append(name, details, source);
+  item.append(text, actions);
+  return item;
+}
+
+async function refresh() {
+  try {
+    const downloads = await invoke("get_downloads", { query: search.value });
+    list.replaceChildren(...downloads.map(render));
+    empty.hidden = downloads.length > 0;
+    empty.textContent = search.value.trim() ? "No matching downloads." : "No downloads yet.";
+  } catch (err) {
+    showError(err);
+  }
+}
+
+search.addEventListener("input", refresh);
+listen("downloads-changed", refresh);
+refresh();